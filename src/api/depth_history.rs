@@ -1,189 +1,87 @@
-use axum::{extract::{Query, State}, Json};
-use mongodb::{bson::doc, Collection, Database};
-use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use futures::stream::StreamExt;
-use crate::db::models::{DepthHistoryDocument, DepthHistory};
-
-#[derive(Debug, Deserialize)]
-pub struct DepthHistoryParams {
-    pub interval: Option<String>,
-    pub limit: Option<usize>,  // Limit for pagination
-    pub page: Option<usize>,   // Pagination page
-    pub from: Option<i64>,
-    pub to: Option<i64>,
-    pub sort_by: Option<String>, // Sorting field
-    pub order: Option<String>,   // "asc" or "desc"
-    pub filters: Option<Vec<String>>, // Example: ["assetDepth>1000", "runeDepth<500"]
-}
-
-/// **Response Meta**
-#[derive(Debug, Serialize)]
-pub struct DepthHistoryMetaResponse {
-    #[serde(rename = "startTime")]
-    pub start_time: i64,
-    #[serde(rename = "endTime")]
-    pub end_time: i64,
-}
-
-/// **Response Structure**
-#[derive(Debug, Serialize)]
-pub struct DepthHistoryResponse {
-    pub meta: DepthHistoryMetaResponse,
-    pub intervals: Vec<DepthHistory>,
-}
-
-/// Converts interval type to seconds
-fn interval_to_seconds(interval: &str) -> Option<i64> {
-    match interval {
-        "5min" => Some(300),
-        "hour" => Some(3600),
-        "day" => Some(86400),
-        "week" => Some(86400 * 7),
-        "month" => Some(86400 * 30),
-        "quarter" => Some(86400 * 90),
-        "year" => Some(86400 * 365),
-        _ => None,
-    }
-}
-
-/// Handles GET /api/depth-history
-pub async fn get_depth_history(
-    State(db): State<Arc<Database>>,  
-    Query(params): Query<DepthHistoryParams>,
-) -> Json<DepthHistoryResponse> {
-    let collection: Collection<DepthHistoryDocument> = db.collection("depth_history");
-
-    let limit = params.limit.unwrap_or(10);
-    let page = params.page.unwrap_or(1).max(1);
-    let interval_seconds = params.interval.as_deref().and_then(interval_to_seconds).unwrap_or(3600);
-
-    let from = params.from.map(|f| f - (f % interval_seconds)).unwrap_or(0);
-    let to = params.to.unwrap_or(i64::MAX);
-
-    let mut pipeline = vec![];
-
-    // **Filter documents based on `from` and `to` time range**
-    pipeline.push(doc! {
-        "$match": {
-            "intervals.startTime": { "$gte": from },
-            "intervals.endTime": { "$lte": to }
+use mongodb::bson::Document;
+use crate::api::history::{Aggregation, HistorySeries, Metric, RangeSummary};
+use crate::api::midgard_compat::MidgardSeries;
+use crate::db::models::{DepthHistory, DepthHistoryMeta, HistoryKind};
+use crate::db::store::{get_amount, get_f64, get_i64};
+
+/// GET /api/depth-history: depths, prices and units of one pool (`pool`, default BTC.BTC)
+pub struct DepthSeries;
+
+impl HistorySeries for DepthSeries {
+    const KIND: HistoryKind = HistoryKind::Depth;
+    const PER_POOL: bool = true;
+    // Depths, units and member counts are point-in-time gauges: a bucket reports its closing value.
+    // Prices use a time-weighted average over the bucket.
+    const METRICS: &'static [Metric] = &[
+        Metric::new("assetDepth", Aggregation::Last),
+        Metric::new("runeDepth", Aggregation::Last),
+        Metric::new("liquidityUnits", Aggregation::Last),
+        Metric::new("synthSupply", Aggregation::Last),
+        Metric::new("synthUnits", Aggregation::Last),
+        Metric::new("units", Aggregation::Last),
+        Metric::new("membersCount", Aggregation::Last),
+        Metric::new("luvi", Aggregation::Last),
+        Metric::new("assetPrice", Aggregation::TimeWeightedAvg),
+        Metric::new("assetPriceUSD", Aggregation::TimeWeightedAvg),
+    ];
+
+    type Interval = DepthHistory;
+    type Meta = DepthHistoryMeta;
+
+    fn interval(doc: &Document) -> DepthHistory {
+        DepthHistory {
+            asset_depth: get_amount(doc, "assetDepth"),
+            asset_price: get_f64(doc, "assetPrice"),
+            asset_price_usd: get_f64(doc, "assetPriceUSD"),
+            liquidity_units: get_amount(doc, "liquidityUnits"),
+            members_count: get_i64(doc, "membersCount"),
+            rune_depth: get_amount(doc, "runeDepth"),
+            start_time: get_i64(doc, "startTime"),
+            end_time: get_i64(doc, "endTime"),
+            synth_supply: get_amount(doc, "synthSupply"),
+            synth_units: get_amount(doc, "synthUnits"),
+            total_units: get_amount(doc, "units"),
+            luvi: get_f64(doc, "luvi"),
         }
-    });
-
-    // **Unwind intervals array**
-    pipeline.push(doc! { "$unwind": "$intervals" });
+    }
 
-    // **Filter intervals to ensure they start after the rounded `from` timestamp**
-    pipeline.push(doc! {
-        "$match": {
-            "intervals.startTime": { "$gte": from }
-        }
-    });
+    fn meta(summary: &RangeSummary) -> DepthHistoryMeta {
+        let (opening, closing) = (&summary.opening, &summary.closing);
 
-    // **Apply dynamic filters**
-    if let Some(filters) = &params.filters {
-        let mut filter_conditions = vec![];
-        for filter in filters {
-            let parts: Vec<&str> = filter.split(|c| c == '>' || c == '<' || c == '=').collect();
-            if parts.len() == 2 {
-                let field = parts[0].trim();
-                let value: f64 = parts[1].trim().parse().unwrap_or(0.0);
-                let operator = if filter.contains(">=") {
-                    "$gte"
-                } else if filter.contains("<=") {
-                    "$lte"
-                } else if filter.contains(">") {
-                    "$gt"
-                } else if filter.contains("<") {
-                    "$lt"
-                } else {
-                    "$eq"
-                };
+        // **LUVI change** over the range
+        let start_luvi = get_f64(opening, "luvi");
+        let luvi_increase = if start_luvi > 0.0 { get_f64(closing, "luvi") / start_luvi } else { 1.0 };
 
-                filter_conditions.push(doc! { format!("intervals.{}", field): { operator: value } });
+        // **Price shift loss**: value kept by a position held over the range, from the change in depth ratio
+        let price = |doc: &Document| {
+            let asset_depth = get_f64(doc, "assetDepth");
+            (asset_depth > 0.0).then(|| get_f64(doc, "runeDepth") / asset_depth)
+        };
+        let price_shift_loss = match (price(opening), price(closing)) {
+            (Some(start), Some(end)) if start > 0.0 => {
+                let ratio = end / start;
+                2.0 * ratio.sqrt() / (1.0 + ratio)
             }
-        }
-        if !filter_conditions.is_empty() {
-            pipeline.push(doc! { "$match": { "$and": filter_conditions } });
-        }
-    }
-
-    // **Group by interval boundaries**
-    pipeline.push(doc! {
-        "$group": {
-            "_id": {
-                "intervalStart": {
-                    "$subtract": [
-                        "$intervals.startTime",
-                        { "$mod": ["$intervals.startTime", interval_seconds] }
-                    ]
-                }
-            },
-            "assetDepth": { "$sum": "$intervals.assetDepth" },
-            "runeDepth": { "$sum": "$intervals.runeDepth" },
-            "liquidityUnits": { "$sum": "$intervals.liquidityUnits" },
-            "synthSupply": { "$sum": "$intervals.synthSupply" },
-            "synthUnits": { "$sum": "$intervals.synthUnits" },
-            "totalUnits": { "$sum": "$intervals.units" },
-            "membersCount": { "$avg": "$intervals.membersCount" },
-            "assetPrice": { "$avg": "$intervals.assetPrice" },
-            "assetPriceUSD": { "$avg": "$intervals.assetPriceUSD" },
-            "luvi": { "$avg": "$intervals.luvi" },
-            "startTime": { "$min": "$intervals.startTime" },
-            "endTime": { "$max": "$intervals.endTime" }
-        }
-    });
-
-    // **Sorting**
-    if let Some(sort_by) = &params.sort_by {
-        let sort_order = match params.order.as_deref() {
-            Some("desc") => -1,
-            _ => 1,
+            _ => 1.0,
         };
-        pipeline.push(doc! { "$sort": { sort_by: sort_order } });
-    } else {
-        pipeline.push(doc! { "$sort": { "_id.intervalStart": 1 } });
-    }
-
-    // **Pagination**
-    let skip_count = (page - 1) * limit;
-    pipeline.push(doc! { "$skip": skip_count as i64 });
-    pipeline.push(doc! { "$limit": limit as i64 });
-
-    // **Execute the pipeline**
-    let mut cursor = collection.aggregate(pipeline, None).await.unwrap();
-    let mut intervals = Vec::new();
-    let mut meta_start_time = None;
-    let mut meta_end_time = None;
 
-    while let Some(Ok(doc)) = cursor.next().await {
-        let interval = DepthHistory {
-            asset_depth: doc.get_f64("assetDepth").unwrap_or(0.0),
-            asset_price: doc.get_f64("assetPrice").unwrap_or(0.0),
-            asset_price_usd: doc.get_f64("assetPriceUSD").unwrap_or(0.0),
-            liquidity_units: doc.get_f64("liquidityUnits").unwrap_or(0.0),
-            members_count: doc.get_f64("membersCount").unwrap_or(0.0) as i32,
-            rune_depth: doc.get_f64("runeDepth").unwrap_or(0.0),
-            start_time: doc.get_i64("startTime").unwrap_or(0),
-            end_time: doc.get_i64("endTime").unwrap_or(0),
-            synth_supply: doc.get_f64("synthSupply").unwrap_or(0.0),
-            synth_units: doc.get_f64("synthUnits").unwrap_or(0.0),
-            total_units: doc.get_f64("totalUnits").unwrap_or(0.0),
-            luvi: doc.get_f64("luvi").unwrap_or(0.0),
-        };
-        if intervals.is_empty() {
-            meta_start_time = Some(interval.start_time);
+        DepthHistoryMeta {
+            start_time: summary.start_time,
+            end_time: summary.end_time,
+            start_asset_depth: get_amount(opening, "assetDepth"),
+            start_rune_depth: get_amount(opening, "runeDepth"),
+            start_lp_units: get_amount(opening, "liquidityUnits"),
+            start_synth_units: get_amount(opening, "synthUnits"),
+            start_member_count: get_i64(opening, "membersCount"),
+            end_asset_depth: get_amount(closing, "assetDepth"),
+            end_rune_depth: get_amount(closing, "runeDepth"),
+            end_lp_units: get_amount(closing, "liquidityUnits"),
+            end_synth_units: get_amount(closing, "synthUnits"),
+            end_member_count: get_i64(closing, "membersCount"),
+            luvi_increase,
+            price_shift_loss,
         }
-        meta_end_time = Some(interval.end_time);
-        intervals.push(interval);
     }
+}
 
-    // **Build response meta**
-    let meta = DepthHistoryMetaResponse {
-        start_time: meta_start_time.unwrap_or(from),
-        end_time: meta_end_time.unwrap_or(to),
-    };
-
-    Json(DepthHistoryResponse { meta, intervals })
-}
\ No newline at end of file
+impl MidgardSeries for DepthSeries {}
@@ -0,0 +1,276 @@
+use mongodb::bson::Bson;
+
+use crate::db::amount::Amount;
+use crate::db::store::{Comparison, Filter};
+
+/// Parses a filter expression such as `assetDepth>=1e12, (swapCount=1..10 | units in (5, 6))`.
+///
+/// - comparisons: `field > value`, with `>`, `>=`, `<`, `<=`, `=` and `!=`
+/// - ranges: `field=low..high`, both ends included
+/// - sets: `field in (a, b, c)`
+/// - `,` or `and` binds tighter than `|` or `or`; parentheses group
+///
+/// Integers stay exact (beyond 2^53 too); errors name the 1-based column they were found at.
+pub fn parse_filter(input: &str) -> Result<Filter, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, position: 0, end: input.chars().count() + 1 };
+    let filter = parser.any()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(filter),
+        Some((_, column)) => Err(format!("unexpected input at column {}", column)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Field(String),
+    Number(Bson),
+    Op(Comparison),
+    Range,
+    Open,
+    Close,
+    And,
+    Or,
+    In,
+}
+
+/// Tokens with the column they start at
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let column = i + 1;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, width) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            ',' => (Token::And, 1),
+            '|' => (Token::Or, if next == Some('|') { 2 } else { 1 }),
+            '>' if next == Some('=') => (Token::Op(Comparison::Gte), 2),
+            '<' if next == Some('=') => (Token::Op(Comparison::Lte), 2),
+            '!' if next == Some('=') => (Token::Op(Comparison::Ne), 2),
+            '=' if next == Some('=') => (Token::Op(Comparison::Eq), 2),
+            '>' => (Token::Op(Comparison::Gt), 1),
+            '<' => (Token::Op(Comparison::Lt), 1),
+            '=' => (Token::Op(Comparison::Eq), 1),
+            '.' if next == Some('.') => (Token::Range, 2),
+            c if c.is_ascii_digit() || (matches!(c, '-' | '+') && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let width = number_width(&chars[i..]);
+                let text: String = chars[i..i + width].iter().collect();
+                (Token::Number(number(&text).ok_or_else(|| format!("`{}` at column {} is out of range", text, column))?), width)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let width = chars[i..].iter().take_while(|c| c.is_ascii_alphanumeric() || **c == '_').count();
+                let word: String = chars[i..i + width].iter().collect();
+                let token = match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "in" => Token::In,
+                    _ => Token::Field(word),
+                };
+                (token, width)
+            }
+            other => return Err(format!("unexpected `{}` at column {}", other, column)),
+        };
+        tokens.push((token, column));
+        i += width;
+    }
+
+    Ok(tokens)
+}
+
+/// Length of the number literal at the start of `chars`; a `..` range stops it
+fn number_width(chars: &[char]) -> usize {
+    let digits = |from: usize| chars[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let mut width = if matches!(chars[0], '-' | '+') { 1 } else { 0 };
+    width += digits(width);
+    if chars.get(width) == Some(&'.') && chars.get(width + 1).is_some_and(|c| c.is_ascii_digit()) {
+        width += 1 + digits(width + 1);
+    }
+    if matches!(chars.get(width), Some('e' | 'E')) {
+        let sign = usize::from(matches!(chars.get(width + 1), Some('-' | '+')));
+        let exponent = digits(width + 1 + sign);
+        if exponent > 0 {
+            width += 1 + sign + exponent;
+        }
+    }
+    width
+}
+
+/// Integers become `Int64` (or an exact `Decimal128` when larger), anything else a `Double`
+fn number(text: &str) -> Option<Bson> {
+    if text.bytes().all(|b| b.is_ascii_digit() || b == b'-' || b == b'+') {
+        return match text.parse::<i64>() {
+            Ok(value) => Some(Bson::Int64(value)),
+            Err(_) => Amount::parse(text).map(Bson::from),
+        };
+    }
+    text.parse::<f64>().ok().filter(|value| value.is_finite()).map(Bson::Double)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Column reported when the input ends too early
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).map(|(_, column)| *column).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        let column = self.column();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected {} at column {}", what, column)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Bson, String> {
+        let column = self.column();
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            _ => Err(format!("expected a number at column {}", column)),
+        }
+    }
+
+    /// `all ('|' all)*`
+    fn any(&mut self) -> Result<Filter, String> {
+        let mut filters = vec![self.all()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filters.push(self.all()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::Any(filters) })
+    }
+
+    /// `term (',' term)*`
+    fn all(&mut self) -> Result<Filter, String> {
+        let mut filters = vec![self.term()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filters.push(self.term()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { Filter::All(filters) })
+    }
+
+    /// `'(' any ')'`, `field op value`, `field=low..high` or `field in (values)`
+    fn term(&mut self) -> Result<Filter, String> {
+        let column = self.column();
+        let field = match self.next() {
+            Some(Token::Open) => {
+                let filter = self.any()?;
+                self.expect(Token::Close, "`)`")?;
+                return Ok(filter);
+            }
+            Some(Token::Field(field)) => field,
+            _ => return Err(format!("expected a field name at column {}", column)),
+        };
+
+        let column = self.column();
+        match self.next() {
+            Some(Token::In) => {
+                self.expect(Token::Open, "`(` after `in`")?;
+                let mut values = vec![self.value()?];
+                while self.peek() == Some(&Token::And) {
+                    self.next();
+                    values.push(self.value()?);
+                }
+                self.expect(Token::Close, "`)` closing the `in` list")?;
+                Ok(Filter::In { field, values })
+            }
+            Some(Token::Op(op)) => {
+                let value = self.value()?;
+                if op != Comparison::Eq || self.peek() != Some(&Token::Range) {
+                    return Ok(Filter::Compare { field, op, value });
+                }
+                self.next();
+                let high = self.value()?;
+                Ok(Filter::All(vec![
+                    Filter::Compare { field: field.clone(), op: Comparison::Gte, value },
+                    Filter::Compare { field, op: Comparison::Lte, value: high },
+                ]))
+            }
+            _ => Err(format!("expected an operator or `in` after `{}` at column {}", field, column)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn compare(field: &str, op: Comparison, value: Bson) -> Filter {
+        Filter::Compare { field: field.to_string(), op, value }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let filter = parse_filter("units>0, count=5 | units<3").unwrap();
+        assert_eq!(
+            filter,
+            Filter::Any(vec![
+                Filter::All(vec![compare("units", Comparison::Gt, Bson::Int64(0)), compare("count", Comparison::Eq, Bson::Int64(5))]),
+                compare("units", Comparison::Lt, Bson::Int64(3)),
+            ])
+        );
+        // Parentheses and the word forms regroup it
+        let grouped = parse_filter("units>0 and (count==5 or units<3)").unwrap();
+        assert!(matches!(grouped, Filter::All(ref filters) if matches!(filters[1], Filter::Any(_))));
+    }
+
+    #[test]
+    fn ranges_and_sets() {
+        assert_eq!(
+            parse_filter("swapCount=1..10").unwrap(),
+            Filter::All(vec![compare("swapCount", Comparison::Gte, Bson::Int64(1)), compare("swapCount", Comparison::Lte, Bson::Int64(10))])
+        );
+        assert_eq!(
+            parse_filter("units in (5, -6, 1.5e3)").unwrap(),
+            Filter::In { field: "units".to_string(), values: vec![Bson::Int64(5), Bson::Int64(-6), Bson::Double(1500.0)] }
+        );
+    }
+
+    #[test]
+    fn integers_beyond_f64_stay_exact() {
+        let filter = parse_filter("runeDepth>18014398509481984").unwrap();
+        assert!(filter.matches(&doc! { "runeDepth": 18014398509481985_i64 }));
+        assert!(!filter.matches(&doc! { "runeDepth": 18014398509481984_i64 }));
+
+        let huge = parse_filter("runeDepth<=99999999999999999999").unwrap();
+        assert!(matches!(huge, Filter::Compare { value: Bson::Decimal128(_), .. }));
+        // Missing fields never match
+        assert!(!huge.matches(&doc! {}));
+    }
+
+    #[test]
+    fn errors_name_the_column() {
+        assert_eq!(parse_filter("units>").unwrap_err(), "expected a number at column 7");
+        assert_eq!(parse_filter("units>1 units<2").unwrap_err(), "unexpected input at column 9");
+        assert_eq!(parse_filter("(units>1").unwrap_err(), "expected `)` at column 9");
+        assert_eq!(parse_filter("units # 1").unwrap_err(), "unexpected `#` at column 7");
+        assert_eq!(parse_filter("units 5").unwrap_err(), "expected an operator or `in` after `units` at column 7");
+        assert!(parse_filter("units>1e999").unwrap_err().contains("out of range"));
+    }
+}
@@ -0,0 +1,655 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::State, Json};
+use chrono::Utc;
+use chrono_tz::Tz;
+use mongodb::bson::{Bson, Document};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::cursor::{Cursor, CursorDirection};
+use crate::api::midgard_compat::{fill_buckets, MidgardParams, Window};
+use crate::api::{query::{Fill, FilterStage, HistoryParams, NumberFormat}, AppState};
+use crate::db::amount::Amount;
+use crate::db::models::{HistoryKind, DEFAULT_POOL};
+use crate::db::store::{bson_to_i64, get_i64, Accumulator, AggregateOp, AggregateQuery, Breakdown, BucketUnit, Filter, HistoryStore};
+use crate::error::{ApiError, ParameterError};
+
+/// How a metric is rolled up when several hourly intervals fall into one bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Flows (volumes, fees, counts of events): added up
+    Sum,
+    /// Arithmetic mean of the hourly values
+    Avg,
+    /// Point-in-time values (gauges): the value at the end of the bucket
+    Last,
+    /// Gauges where the bucket's typical level matters more than its close (prices):
+    /// mean weighted by each interval's duration
+    TimeWeightedAvg,
+    /// Arrays of per-pool entries (e.g. earnings per pool): one entry per pool with these fields added up
+    SumByPool(&'static [&'static str]),
+}
+
+impl Aggregation {
+    /// The store accumulator; `None` for the per-pool breakdown, which is a `Breakdown` instead
+    pub(crate) fn op(self) -> Option<AggregateOp> {
+        match self {
+            Aggregation::Sum => Some(AggregateOp::Sum),
+            Aggregation::Avg => Some(AggregateOp::Avg),
+            Aggregation::Last => Some(AggregateOp::Last),
+            Aggregation::TimeWeightedAvg => Some(AggregateOp::TimeWeightedAvg),
+            Aggregation::SumByPool(_) => None,
+        }
+    }
+
+    /// Name reported in the response `meta.aggregation`
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregation::Sum => "sum",
+            Aggregation::Avg => "avg",
+            Aggregation::Last => "last",
+            Aggregation::TimeWeightedAvg => "timeWeightedAvg",
+            Aggregation::SumByPool(_) => "sumByPool",
+        }
+    }
+}
+
+/// One field of a history interval, named the same in storage, buckets and responses
+#[derive(Debug, Clone, Copy)]
+pub struct Metric {
+    pub name: &'static str,
+    pub aggregation: Aggregation,
+}
+
+impl Metric {
+    pub const fn new(name: &'static str, aggregation: Aggregation) -> Self {
+        Self { name, aggregation }
+    }
+}
+
+/// Describes one history type: where it is stored, its metrics and how a bucket becomes a response interval.
+/// Adding a history endpoint only takes a new descriptor and a route.
+pub trait HistorySeries: Send + Sync + 'static {
+    const KIND: HistoryKind;
+    /// Whether intervals are stored per pool, so the `pool` parameter applies
+    const PER_POOL: bool;
+    const METRICS: &'static [Metric];
+
+    type Interval: Serialize + Send;
+    type Meta: Serialize + Send;
+
+    /// Builds a response interval from an aggregated bucket
+    fn interval(bucket: &Document) -> Self::Interval;
+
+    /// Builds the response `meta` summarizing a whole queried range
+    fn meta(summary: &RangeSummary) -> Self::Meta;
+
+}
+
+/// Store accumulators computing `metrics` per bucket, plus the per-pool breakdown (narrowed to `pools` when given)
+pub(crate) fn rollups<'a>(metrics: impl IntoIterator<Item = &'a Metric>, pools: &[&str]) -> (Vec<Accumulator>, Option<Breakdown>) {
+    let mut accumulators = vec![];
+    let mut breakdown = None;
+    for metric in metrics {
+        match metric.aggregation {
+            Aggregation::SumByPool(sums) => {
+                breakdown = Some(Breakdown {
+                    field: metric.name.to_string(),
+                    key: "pool".to_string(),
+                    sums: sums.iter().map(|sum| sum.to_string()).collect(),
+                    only: pools.iter().map(|pool| pool.to_string()).collect(),
+                })
+            }
+            aggregation => accumulators.extend(aggregation.op().map(|op| Accumulator::new(metric.name, metric.name, op))),
+        }
+    }
+    (accumulators, breakdown)
+}
+
+/// Stored values a range's `meta` is computed from
+#[derive(Debug, Clone, Default)]
+pub struct RangeSummary {
+    /// `startTime` of the first interval in the range, else the range start
+    pub start_time: i64,
+    /// `endTime` of the last interval in the range, else the range end
+    pub end_time: i64,
+    /// Every metric rolled up over the whole range with its own aggregation
+    pub totals: Document,
+    /// The last interval before the range, if any
+    pub previous: Option<Document>,
+    /// Gauges at the start of the range: `previous`, else the first interval in the range
+    pub opening: Document,
+    /// Gauges at the end of the range: the last interval in the range, else `opening`
+    pub closing: Document,
+}
+
+/// Summarizes the intervals `query` selects (pool, time range, interval filter), ignoring its bucketing, bucket filter and paging.
+/// One scalar pass over the range (plus the per-pool breakdown, covering only `pools` when given).
+pub async fn summarize_range<H: HistorySeries, S: HistoryStore>(store: &S, query: &AggregateQuery, pools: &[&str]) -> Result<RangeSummary, ApiError> {
+    let previous = store.latest_before(H::KIND, query.pool.as_deref(), query.from).await?;
+
+    let (mut accumulators, breakdown) = rollups(H::METRICS, pools);
+    // Opening gauges come from the interval before the range; only without one are they read from the range itself
+    let gauges: Vec<&str> = H::METRICS.iter().filter(|metric| metric.aggregation == Aggregation::Last).map(|metric| metric.name).collect();
+    if previous.is_none() {
+        accumulators.extend(gauges.iter().map(|name| Accumulator::new(&format!("{}__first", name), name, AggregateOp::First)));
+    }
+    accumulators.push(Accumulator::new("startTime", "startTime", AggregateOp::Min));
+    accumulators.push(Accumulator::new("endTime", "endTime", AggregateOp::Max));
+
+    let whole_range =
+        AggregateQuery { bucket: None, having: None, accumulators, breakdown, sort: vec![], after: None, skip: 0, limit: 1, ..query.clone() };
+    let mut totals = store.aggregate(H::KIND, &whole_range).await?.into_iter().next();
+
+    let first = totals.as_mut().map(|totals| {
+        gauges.iter().filter_map(|name| Some((name.to_string(), totals.remove(format!("{}__first", name))?))).collect::<Document>()
+    });
+    let opening = previous.clone().or(first).unwrap_or_default();
+    let closing = totals.clone().unwrap_or_else(|| opening.clone());
+
+    Ok(RangeSummary {
+        start_time: totals.as_ref().map(|totals| get_i64(totals, "startTime")).unwrap_or(query.from),
+        // An open-ended range without data ends now, not at the end of time
+        end_time: totals.as_ref().map(|totals| get_i64(totals, "endTime")).unwrap_or(query.to.min(Utc::now().timestamp())),
+        totals: totals.unwrap_or_default(),
+        previous,
+        opening,
+        closing,
+    })
+}
+
+/// `startTime` of the first interval `query` selects, without summarizing the range
+async fn first_start_time<H: HistorySeries, S: HistoryStore>(store: &S, query: &AggregateQuery) -> Result<Option<i64>, ApiError> {
+    let accumulators = vec![Accumulator::new("startTime", "startTime", AggregateOp::Min)];
+    let whole_range = AggregateQuery {
+        bucket: None,
+        having: None,
+        accumulators,
+        breakdown: None,
+        sort: vec![],
+        after: None,
+        skip: 0,
+        limit: 1,
+        ..query.clone()
+    };
+    let first = store.aggregate(H::KIND, &whole_range).await?.into_iter().next();
+    Ok(first.as_ref().and_then(|first| first.get("startTime")).and_then(bson_to_i64))
+}
+
+/// **Response Meta**: the series' summary of the queried range plus the rollup of each field
+#[derive(Debug, Serialize)]
+pub struct HistoryMetaResponse<M> {
+    /// Absent with `meta=false`
+    #[serde(flatten)]
+    pub summary: Option<M>,
+    /// How each field was rolled up into the returned intervals (`sum`, `avg`, `last`, ...)
+    pub aggregation: BTreeMap<&'static str, &'static str>,
+    pub pagination: Pagination,
+}
+
+/// Where the returned page sits among all buckets of the range
+#[derive(Debug, Serialize)]
+pub struct Pagination {
+    /// Number of buckets in the whole range; `null` with `meta=false`, which skips counting them
+    pub total: Option<u64>,
+    /// Cursor for the following page, `null` on the last one
+    pub next: Option<String>,
+    /// Cursor for the preceding page, `null` on the first one
+    pub prev: Option<String>,
+}
+
+/// **Response Structure**
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse<I, M> {
+    pub meta: HistoryMetaResponse<M>,
+    pub intervals: Vec<I>,
+}
+
+/// Fields a client may sort by: the series' scalar metrics plus the bucket times
+pub fn sortable_fields<H: HistorySeries>() -> Vec<&'static str> {
+    let mut fields = vec!["startTime", "endTime"];
+    fields.extend(H::METRICS.iter().filter(|metric| metric.aggregation.op().is_some()).map(|metric| metric.name));
+    fields
+}
+
+/// Fields `fields` may select: every field of a response interval
+pub fn projectable_fields<H: HistorySeries>() -> Vec<&'static str> {
+    let mut fields = vec!["startTime", "endTime"];
+    fields.extend(H::METRICS.iter().map(|metric| metric.name));
+    fields
+}
+
+/// Builds the store query for `params`: the requested metrics (all by default), those sorted or filtered on,
+/// and each bucket's `startTime`/`endTime`.
+/// Fails with a 400 when a sort key, filter field or projected field isn't one of the series' fields.
+pub fn aggregate_query<H: HistorySeries>(params: &HistoryParams) -> Result<AggregateQuery, ApiError> {
+    let pool = H::PER_POOL.then(|| params.pool.clone().unwrap_or_else(|| DEFAULT_POOL.to_string()));
+
+    let sortable = sortable_fields::<H>();
+    let projectable = projectable_fields::<H>();
+    let sort_fields = params.sort.iter().map(|(field, _)| ("sort", field.as_str(), &sortable));
+    let filter_fields = params.filter.iter().flat_map(Filter::fields).map(|field| ("filters", field, &sortable));
+    let projected_fields = params.fields.iter().flatten().map(|field| ("fields", field.as_str(), &projectable));
+    let mut invalid: Vec<ParameterError> = sort_fields
+        .chain(filter_fields)
+        .chain(projected_fields)
+        .filter(|(_, field, allowed)| !allowed.contains(field))
+        .map(|(parameter, field, allowed)| ParameterError {
+            parameter: parameter.to_string(),
+            message: format!("unknown field `{}` (expected one of {})", field, allowed.join(", ")),
+        })
+        .collect();
+
+    // `pool` picks the pool of a per-pool series, or narrows a network-wide series' per-pool breakdown
+    let breakdown = H::METRICS.iter().any(|metric| matches!(metric.aggregation, Aggregation::SumByPool(_)));
+    let pool_error = match (&params.pool, H::PER_POOL) {
+        (Some(_), true) if params.pools().len() != 1 => Some("must name exactly one pool"),
+        (Some(_), false) if !breakdown => Some("isn't supported: this history has no per-pool data"),
+        _ => None,
+    };
+    if let Some(message) = pool_error {
+        invalid.push(ParameterError { parameter: "pool".to_string(), message: message.to_string() });
+    }
+    if !invalid.is_empty() {
+        return Err(ApiError::InvalidParameter(invalid));
+    }
+
+    // **Projection**: metrics nobody asked for are never aggregated
+    let needed = |name: &str| {
+        params.fields.as_ref().is_none_or(|fields| fields.iter().any(|field| field == name))
+            || params.sort.iter().any(|(field, _)| field == name)
+            || (params.filter_on == FilterStage::Buckets && params.filter.iter().flat_map(Filter::fields).any(|field| field == name))
+    };
+    let (mut accumulators, breakdown) = rollups(H::METRICS.iter().filter(|metric| needed(metric.name)), &params.pools());
+    accumulators.push(Accumulator::new("startTime", "startTime", AggregateOp::Min));
+    accumulators.push(Accumulator::new("endTime", "endTime", AggregateOp::Max));
+
+    // **Sorting**: by the requested keys, else chronologically; the bucket start breaks ties so cursors are exact
+    let mut sort = params.sort.clone();
+    sort.push(("_id".to_string(), params.order));
+
+    Ok(AggregateQuery {
+        pool,
+        // Align `from` to the bucket boundary so the first bucket is complete
+        from: params.from.map(|f| params.interval.bucket_start(params.timezone, f)).unwrap_or(0),
+        to: params.to.unwrap_or(i64::MAX),
+        bucket: Some(params.interval),
+        timezone: params.timezone,
+        filter: params.filter.clone().filter(|_| params.filter_on == FilterStage::Intervals),
+        having: params.filter.clone().filter(|_| params.filter_on == FilterStage::Buckets),
+        accumulators,
+        breakdown,
+        sort,
+        after: None,
+        skip: params.skip(),
+        limit: params.limit as u64,
+    })
+}
+
+/// Most buckets `fill` may produce for one request
+const MAX_FILLED_BUCKETS: usize = 100_000;
+
+/// Every `unit` bucket from the one containing `from` up to `to`, as `(startTime, endTime)`; the last one ends at `to`
+fn dense_buckets(unit: BucketUnit, tz: Tz, from: i64, to: i64) -> Result<Vec<(i64, i64)>, ApiError> {
+    let mut buckets = vec![];
+    let mut start = unit.bucket_start(tz, from);
+    while start < to {
+        if buckets.len() >= MAX_FILLED_BUCKETS {
+            return Err(ApiError::invalid_parameter(
+                "fill",
+                format!("the range covers more than {} intervals; narrow `from`/`to` or use a larger `interval`", MAX_FILLED_BUCKETS),
+            ));
+        }
+        let end = unit.next_bucket_start(tz, start);
+        buckets.push((start, end.min(to)));
+        start = end;
+    }
+    Ok(buckets)
+}
+
+/// `interval` as JSON, keeping only `fields` when given
+fn project<I: Serialize>(interval: I, fields: Option<&[String]>) -> Result<Value, serde_json::Error> {
+    let value = serde_json::to_value(interval)?;
+    Ok(match (value, fields) {
+        (Value::Object(mut object), Some(fields)) => {
+            object.retain(|key, _| fields.iter().any(|field| field == key));
+            Value::Object(object)
+        }
+        (value, _) => value,
+    })
+}
+
+/// Turns every amount string in `value` into a JSON number (`numbers=float`)
+fn amounts_as_floats(value: Value) -> Value {
+    match value {
+        Value::String(s) => match Amount::parse(&s) {
+            Some(amount) if s.bytes().all(|b| b.is_ascii_digit() || b == b'-') => Value::from(amount.as_f64()),
+            _ => Value::String(s),
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(amounts_as_floats).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, v)| (key, amounts_as_floats(v))).collect()),
+        other => other,
+    }
+}
+
+/// Handles GET on every history endpoint
+pub async fn get_history<H: HistorySeries, S: HistoryStore>(
+    State(state): State<AppState<S>>,
+    params: HistoryParams,
+) -> Result<Json<Value>, ApiError> {
+    let mut query = aggregate_query::<H>(&params)?;
+
+    // **Fixed number of buckets** (`count`): Midgard's window
+    let window = match params.count {
+        Some(count) => {
+            let midgard = MidgardParams { interval: Some(params.interval), count: Some(count), from: params.from, to: params.to };
+            let window = Window::resolve(&midgard, params.timezone, Utc::now().timestamp())?;
+            query.from = window.from;
+            query.to = window.to;
+            query.sort = vec![("_id".to_string(), params.order)];
+            query.skip = 0;
+            Some(window)
+        }
+        None => None,
+    };
+    // **Range summary** for `meta`, skipped with `meta=false`
+    let summary = match params.meta {
+        true => Some(summarize_range::<H, S>(state.store.as_ref(), &query, &params.pools()).await?),
+        false => None,
+    };
+
+    // **Gap filling**: `count` and `fill` return every bucket of the range, with or without data
+    let fill = params.fill.or(params.count.map(|_| Fill::Previous));
+    let dense = match (&window, fill) {
+        (Some(window), _) => Some(window.buckets.clone()),
+        // From `from` (else the first stored interval) up to `to` (else now)
+        (None, Some(_)) => {
+            let first_stored = match &summary {
+                Some(summary) => (!summary.totals.is_empty()).then_some(summary.start_time),
+                None if params.from.is_none() => first_start_time::<H, S>(state.store.as_ref(), &query).await?,
+                None => None,
+            };
+            match params.from.map(|_| query.from).or(first_stored) {
+                Some(from) => Some(dense_buckets(params.interval, params.timezone, from, query.to.min(Utc::now().timestamp()))?),
+                None => Some(vec![]),
+            }
+        }
+        (None, None) => None,
+    };
+    let total = match &dense {
+        Some(dense) => Some(dense.len() as u64),
+        None if params.meta => Some(state.store.count_buckets(H::KIND, &query).await?),
+        None => None,
+    };
+
+    // **Keyset pagination**: continue strictly after (or before) the cursor's bucket
+    let sort = query.sort.clone();
+    let direction = params.cursor.as_ref().map(|cursor| cursor.direction);
+    if let Some(cursor) = &params.cursor {
+        if cursor.sort != sort {
+            return Err(ApiError::invalid_parameter("cursor", "was issued for a different sort order"));
+        }
+        query.skip = 0;
+        query.after = Some(cursor.keys.clone());
+        if cursor.direction == CursorDirection::Prev {
+            // Walk backwards from the cursor and restore the order afterwards
+            query.sort.iter_mut().for_each(|(_, order)| *order = -*order);
+        }
+        // Chronological keysets also narrow the scanned time range
+        let chronological = query.sort.first().filter(|(field, _)| field == "_id").map(|(_, order)| *order);
+        if let (Some(order), Some(id)) = (chronological, cursor.keys.first().and_then(bson_to_i64)) {
+            if order > 0 {
+                query.from = query.from.max(params.interval.next_bucket_start(params.timezone, id));
+            } else {
+                query.to = query.to.min(id);
+            }
+        }
+    }
+
+    let (mut buckets, has_more) = match dense {
+        Some(dense) => {
+            // Page through the bucket list, then fetch the stored buckets of that page only
+            let mut page: Vec<(i64, i64)> = dense.into_iter().filter(|(start, _)| *start >= query.from && *start < query.to).collect();
+            let descending = query.sort.first().is_some_and(|(_, order)| *order < 0);
+            if descending {
+                page.reverse();
+            }
+            let limit = if params.count.is_some() { page.len() } else { params.limit };
+            let has_more = page.len() > (query.skip as usize).saturating_add(limit);
+            let mut page: Vec<(i64, i64)> = page.into_iter().skip(query.skip as usize).take(limit).collect();
+            if descending {
+                page.reverse();
+            }
+
+            let mut buckets = match (page.first(), page.last()) {
+                (Some(first), Some(last)) => {
+                    let page_query = AggregateQuery {
+                        from: first.0,
+                        to: last.1,
+                        sort: vec![("_id".to_string(), 1)],
+                        after: None,
+                        skip: 0,
+                        limit: page.len() as u64,
+                        ..query.clone()
+                    };
+                    let stored = state.store.aggregate(H::KIND, &page_query).await?;
+                    let opening = state.store.latest_before(H::KIND, query.pool.as_deref(), first.0).await?;
+                    fill_buckets::<H>(&page, stored, opening.as_ref(), fill.unwrap_or(Fill::Previous))
+                }
+                _ => vec![],
+            };
+            if descending {
+                buckets.reverse();
+            }
+            (buckets, has_more)
+        }
+        None => {
+            // One extra bucket tells whether another page follows
+            query.limit += 1;
+            let mut buckets = state.store.aggregate(H::KIND, &query).await?;
+            let has_more = buckets.len() > params.limit;
+            buckets.truncate(params.limit);
+            (buckets, has_more)
+        }
+    };
+    if direction == Some(CursorDirection::Prev) {
+        buckets.reverse();
+    }
+
+    let (more_after, more_before) = match direction {
+        Some(CursorDirection::Prev) => (true, has_more),
+        Some(CursorDirection::Next) => (has_more, true),
+        None => (has_more, params.page > 1),
+    };
+    let pagination = Pagination {
+        total,
+        next: buckets.last().filter(|_| more_after).map(|bucket| Cursor::at(CursorDirection::Next, &sort, bucket).encode()),
+        prev: buckets.first().filter(|_| more_before).map(|bucket| Cursor::at(CursorDirection::Prev, &sort, bucket).encode()),
+    };
+
+    // **Build response meta** over the whole queried range, not just the returned page
+    let meta = HistoryMetaResponse {
+        summary: summary.as_ref().map(H::meta),
+        aggregation: H::METRICS.iter().map(|metric| (metric.name, metric.aggregation.name())).collect(),
+        pagination,
+    };
+    let intervals = buckets
+        .iter()
+        .map(|bucket| {
+            let interval = project(H::interval(bucket), params.fields.as_deref())?;
+            // `fill=null`: typed intervals can't hold a missing value, so restore it here
+            Ok(match (params.fill, interval) {
+                (Some(Fill::Null), Value::Object(mut fields)) => {
+                    for (name, value) in fields.iter_mut() {
+                        if matches!(bucket.get(name), Some(Bson::Null)) {
+                            *value = Value::Null;
+                        }
+                    }
+                    Value::Object(fields)
+                }
+                (_, interval) => interval,
+            })
+        })
+        .collect::<Result<Vec<Value>, serde_json::Error>>()
+        .map_err(|e| ApiError::Internal(format!("Failed to encode interval: {}", e)))?;
+
+    let response = serde_json::to_value(HistoryResponse::<_, H::Meta> { meta, intervals })
+        .map_err(|e| ApiError::Internal(format!("Failed to encode response: {}", e)))?;
+    Ok(Json(match params.numbers {
+        NumberFormat::String => response,
+        NumberFormat::Float => amounts_as_floats(response),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::api::swaps_history::SwapsSeries;
+    use crate::db::memory_store::MemoryHistoryStore;
+    use crate::utils::backfill::{run_backfill, BackfillJob};
+    use crate::utils::test_support::FixtureDir;
+
+    /// 2023-11-14T00:00:00Z
+    const DAY: i64 = 1_699_920_000;
+
+    /// Loads a swaps recording into a fresh in-memory store the way the `backfill` command does
+    async fn ingested(hours: &[(i64, &str)]) -> AppState<MemoryHistoryStore> {
+        let fixtures = FixtureDir::new();
+        fixtures.record_swaps(hours);
+        let store = MemoryHistoryStore::new();
+        let job = BackfillJob { kind: HistoryKind::Swaps, pool: None, from: DAY, to: Some(DAY + 2 * 86_400), concurrency: 1, restart: false };
+        let outcome = run_backfill(&store, &fixtures.client(), &job).await;
+        assert_eq!(outcome.error, None);
+        AppState { store: Arc::new(store), max_limit: 400 }
+    }
+
+    async fn history(state: &AppState<MemoryHistoryStore>, query: &[(&str, &str)]) -> Value {
+        let pairs: Vec<(String, String)> = query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        let params = HistoryParams::from_pairs(&pairs, state.max_limit).unwrap();
+        get_history::<SwapsSeries, MemoryHistoryStore>(State(state.clone()), params).await.unwrap().0
+    }
+
+    #[tokio::test]
+    async fn amounts_sum_exactly_per_day() {
+        // 2^54 + 3 and 2^54 + 5: neither they nor their sum are representable as f64
+        let state = ingested(&[(DAY, "18014398509481987"), (DAY + 3600, "18014398509481989"), (DAY + 86_400, "7")]).await;
+
+        let response = history(&state, &[("interval", "day"), ("from", &DAY.to_string())]).await;
+        let intervals = response["intervals"].as_array().unwrap();
+        assert_eq!(intervals.len(), 2);
+        assert_eq!(intervals[0]["startTime"], DAY);
+        assert_eq!(intervals[0]["totalVolume"], "36028797018963976");
+        assert_eq!(intervals[1]["totalVolume"], "7");
+        assert_eq!(response["meta"]["totalVolume"], "36028797018963983");
+
+        // `numbers=float` opts into JSON numbers
+        let response = history(&state, &[("interval", "day"), ("numbers", "float")]).await;
+        assert_eq!(response["intervals"][1]["totalVolume"], 7.0);
+    }
+
+    #[tokio::test]
+    async fn fill_inserts_the_missing_hours() {
+        let state = ingested(&[(DAY, "5"), (DAY + 3 * 3600, "9")]).await;
+        let window = [("interval", "hour"), ("from", &*DAY.to_string()), ("to", &*(DAY + 4 * 3600).to_string())];
+        let volumes = |response: &Value| -> Vec<String> {
+            response["intervals"].as_array().unwrap().iter().map(|interval| interval["totalVolume"].as_str().unwrap().to_string()).collect()
+        };
+
+        let response = history(&state, &[&window[..], &[("fill", "zero")]].concat()).await;
+        assert_eq!(volumes(&response), vec!["5", "0", "0", "9"]);
+        assert_eq!(response["meta"]["pagination"]["total"], 4);
+        let starts: Vec<i64> = response["intervals"].as_array().unwrap().iter().map(|interval| interval["startTime"].as_i64().unwrap()).collect();
+        assert_eq!(starts, vec![DAY, DAY + 3600, DAY + 7200, DAY + 10_800]);
+
+        // Without `fill` only the stored hours come back
+        assert_eq!(volumes(&history(&state, &window).await), vec!["5", "9"]);
+    }
+
+    #[tokio::test]
+    async fn meta_summarizes_the_whole_range_unless_turned_off() {
+        let state = ingested(&[(DAY, "1"), (DAY + 3600, "2"), (DAY + 7200, "4")]).await;
+
+        // One page of one hour, but `meta` covers all three
+        let response = history(&state, &[("interval", "hour"), ("limit", "1")]).await;
+        assert_eq!(response["intervals"].as_array().unwrap().len(), 1);
+        assert_eq!(response["meta"]["totalVolume"], "7");
+        assert_eq!(response["meta"]["startTime"], DAY);
+        assert_eq!(response["meta"]["endTime"], DAY + 10_800);
+        assert_eq!(response["meta"]["pagination"]["total"], 3);
+
+        let response = history(&state, &[("interval", "hour"), ("limit", "1"), ("meta", "false")]).await;
+        assert!(response["meta"].get("totalVolume").is_none());
+        assert_eq!(response["meta"]["pagination"]["total"], Value::Null);
+        assert!(response["meta"]["pagination"]["next"].is_string());
+    }
+
+    #[tokio::test]
+    async fn cursors_page_through_every_bucket_both_ways() {
+        let hours: Vec<(i64, &str)> = (0..5).map(|hour| (DAY + hour * 3600, "1")).collect();
+        let state = ingested(&hours).await;
+        let starts = |response: &Value| -> Vec<i64> {
+            response["intervals"].as_array().unwrap().iter().map(|interval| interval["startTime"].as_i64().unwrap()).collect()
+        };
+
+        let mut pages = vec![history(&state, &[("interval", "hour"), ("limit", "2")]).await];
+        while let Some(next) = pages.last().unwrap()["meta"]["pagination"]["next"].as_str().map(str::to_string) {
+            pages.push(history(&state, &[("interval", "hour"), ("limit", "2"), ("cursor", &next)]).await);
+        }
+        let visited: Vec<i64> = pages.iter().flat_map(starts).collect();
+        assert_eq!(visited, hours.iter().map(|(start, _)| *start).collect::<Vec<_>>());
+
+        // `prev` from the last page returns the page before it
+        let prev = pages[2]["meta"]["pagination"]["prev"].as_str().unwrap();
+        let response = history(&state, &[("interval", "hour"), ("limit", "2"), ("cursor", prev)]).await;
+        assert_eq!(starts(&response), starts(&pages[1]));
+    }
+
+    /// `(parameter, message)` of every problem a rejected request reports
+    async fn rejected(state: &AppState<MemoryHistoryStore>, query: &[(&str, &str)]) -> Vec<(String, String)> {
+        let pairs: Vec<(String, String)> = query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        let result = match HistoryParams::from_pairs(&pairs, state.max_limit) {
+            Ok(params) => get_history::<SwapsSeries, MemoryHistoryStore>(State(state.clone()), params).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(ApiError::InvalidParameter(errors)) => errors.into_iter().map(|error| (error.parameter, error.message)).collect(),
+            other => panic!("expected a 400, got {:?}", other.map(|_| "a response")),
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_apply_to_intervals_or_to_buckets() {
+        let state = ingested(&[(DAY, "5"), (DAY + 3600, "50"), (DAY + 7200, "5"), (DAY + 86_400, "100")]).await;
+        let volumes = |response: &Value| -> Vec<String> {
+            response["intervals"].as_array().unwrap().iter().map(|interval| interval["totalVolume"].as_str().unwrap().to_string()).collect()
+        };
+
+        // Hourly intervals are filtered before they are added up
+        let response = history(&state, &[("interval", "day"), ("filters", "totalVolume>10")]).await;
+        assert_eq!(volumes(&response), vec!["50", "100"]);
+        let response = history(&state, &[("interval", "hour"), ("filters", "totalVolume=1..10 | totalVolume in (100)")]).await;
+        assert_eq!(volumes(&response), vec!["5", "5", "100"]);
+
+        // `filter_on=buckets` filters the daily sums instead, and the count follows
+        let response = history(&state, &[("interval", "day"), ("filters", "totalVolume>=60"), ("filter_on", "buckets")]).await;
+        assert_eq!(volumes(&response), vec!["60", "100"]);
+        let response = history(&state, &[("interval", "day"), ("filters", "totalVolume>60"), ("filter_on", "buckets")]).await;
+        assert_eq!(volumes(&response), vec!["100"]);
+        assert_eq!(response["meta"]["pagination"]["total"], 1);
+    }
+
+    #[tokio::test]
+    async fn malformed_filters_are_rejected() {
+        let state = ingested(&[(DAY, "1")]).await;
+        let errors = rejected(&state, &[("filters", "totalVolume>")]).await;
+        assert_eq!(errors, vec![("filters".to_string(), "`totalVolume>`: expected a number at column 13".to_string())]);
+        let errors = rejected(&state, &[("filters", "depth>1")]).await;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].1.contains("depth"), "{:?}", errors);
+        let errors = rejected(&state, &[("filters", "totalVolume>1"), ("filter_on", "rows")]).await;
+        assert_eq!(errors[0].0, "filter_on");
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query, State},
+    http::request::Parts,
+    Json,
+};
+use chrono::Utc;
+use chrono_tz::Tz;
+use mongodb::bson::{doc, Bson, Document};
+use serde_json::{Map, Value};
+
+use crate::api::history::{rollups, summarize_range, Aggregation, HistorySeries, RangeSummary};
+use crate::api::query::{Fill, Violations};
+use crate::api::AppState;
+use crate::db::amount::Amount;
+use crate::db::store::{bson_to_f64, get_i64, Accumulator, AggregateOp, AggregateQuery, BucketUnit, HistoryStore};
+use crate::error::ApiError;
+
+/// Midgard never returns more intervals than this
+pub const MAX_COUNT: usize = 400;
+/// Number of intervals when only `interval` (and maybe `to`) is given
+pub const DEFAULT_COUNT: usize = 10;
+
+/// Query parameters of Midgard's `/v2/history/*` endpoints
+#[derive(Debug, Clone)]
+pub struct MidgardParams {
+    pub interval: Option<BucketUnit>,
+    pub count: Option<usize>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl MidgardParams {
+    pub fn from_pairs(pairs: &[(String, String)]) -> Result<Self, ApiError> {
+        let mut violations = Violations::default();
+        let mut params = MidgardParams { interval: None, count: None, from: None, to: None };
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "interval" => match BucketUnit::parse(value.trim()) {
+                    Some(interval) => params.interval = Some(interval),
+                    None => violations.push(
+                        "interval",
+                        format!("unknown interval `{}` (expected 5min, hour, day, week, month, quarter or year)", value),
+                    ),
+                },
+                "count" => match violations.parse_usize("count", value) {
+                    Some(count) if (1..=MAX_COUNT).contains(&count) => params.count = Some(count),
+                    Some(count) => violations.push("count", format!("must be between 1 and {}, got {}", MAX_COUNT, count)),
+                    None => {}
+                },
+                "from" => params.from = violations.parse_timestamp("from", value),
+                "to" => params.to = violations.parse_timestamp("to", value),
+                _ => {}
+            }
+        }
+
+        if params.from.is_some() && params.to.is_some() && params.count.is_some() {
+            violations.push("count", "only two of `from`, `to` and `count` may be given");
+        }
+        if params.interval.is_none() && params.count.is_some() {
+            violations.push("count", "requires `interval`");
+        }
+        if let (Some(from), Some(to)) = (params.from, params.to) {
+            if from > to {
+                violations.push("from", format!("must not be after `to` ({} > {})", from, to));
+            }
+        }
+
+        violations.finish(params)
+    }
+}
+
+#[async_trait]
+impl<S: HistoryStore> FromRequestParts<AppState<S>> for MidgardParams {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState<S>) -> Result<Self, Self::Rejection> {
+        let Query(pairs) = Query::<Vec<(String, String)>>::from_request_parts(parts, state).await?;
+        MidgardParams::from_pairs(&pairs)
+    }
+}
+
+/// Time range of a Midgard response and its buckets as `(startTime, endTime)`
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub from: i64,
+    pub to: i64,
+    pub buckets: Vec<(i64, i64)>,
+}
+
+impl Window {
+    /// Applies Midgard's rules: with an `interval`, `from`/`to` are rounded to bucket
+    /// boundaries and at most `MAX_COUNT` buckets are returned; without one, the exact
+    /// `from..to` range is a single bucket.
+    pub fn resolve(params: &MidgardParams, tz: Tz, now: i64) -> Result<Self, ApiError> {
+        let Some(unit) = params.interval else {
+            let from = params.from.unwrap_or(0);
+            let to = params.to.unwrap_or(now);
+            return Ok(Window { from, to, buckets: vec![(from, to)] });
+        };
+
+        let mut buckets = vec![];
+        match (params.from, params.to, params.count) {
+            // **Walk forward from `from`**, `count` buckets or up to `to`
+            (Some(from), to, count) => {
+                let to = to.unwrap_or(now);
+                let mut start = unit.bucket_start(tz, from);
+                loop {
+                    let done = match count {
+                        Some(count) => buckets.len() >= count,
+                        None => start >= to && !buckets.is_empty(),
+                    };
+                    if done {
+                        break;
+                    }
+                    if buckets.len() >= MAX_COUNT {
+                        return Err(ApiError::invalid_parameter(
+                            "from",
+                            format!("the range covers more than {} intervals; narrow it or use `count`", MAX_COUNT),
+                        ));
+                    }
+                    let end = unit.next_bucket_start(tz, start);
+                    buckets.push((start, end));
+                    start = end;
+                }
+            }
+            // **Walk back** from the bucket containing `to` (exclusive)
+            (None, to, count) => {
+                let to = to.unwrap_or(now);
+                let count = count.unwrap_or(DEFAULT_COUNT);
+                let mut start = unit.bucket_start(tz, to.saturating_sub(1));
+                while buckets.len() < count {
+                    buckets.push((start, unit.next_bucket_start(tz, start)));
+                    start = unit.bucket_start(tz, start.saturating_sub(1));
+                }
+                buckets.reverse();
+            }
+        }
+
+        // The current bucket ends now, not in the future
+        if let Some(last) = buckets.last_mut() {
+            if last.0 < now && last.1 > now {
+                last.1 = now;
+            }
+        }
+
+        let from = buckets.first().map(|bucket| bucket.0).unwrap_or(now);
+        let to = buckets.last().map(|bucket| bucket.1).unwrap_or(now);
+        Ok(Window { from, to, buckets })
+    }
+}
+
+/// Midgard's response layout for one history type, on top of its `HistorySeries` metrics
+pub trait MidgardSeries: HistorySeries {
+    /// Fields of one Midgard interval besides `startTime`/`endTime`
+    fn midgard_fields(bucket: &Document) -> Map<String, Value> {
+        metric_fields::<Self>(bucket)
+    }
+
+    /// Midgard's `meta`: the series' summary of the range with every number as a string
+    fn midgard_meta(summary: &RangeSummary) -> Map<String, Value> {
+        match serde_json::to_value(Self::meta(summary)).map(midgard_strings) {
+            Ok(Value::Object(meta)) => meta,
+            _ => Map::new(),
+        }
+    }
+}
+
+/// Every scalar metric of `H` in `bucket` as a Midgard string
+pub fn metric_fields<H: HistorySeries + ?Sized>(bucket: &Document) -> Map<String, Value> {
+    H::METRICS
+        .iter()
+        .filter(|metric| metric.aggregation.op().is_some())
+        .map(|metric| (metric.name.to_string(), Value::String(midgard_number(bucket.get(metric.name)))))
+        .collect()
+}
+
+/// Formats a stored number the way Midgard does: integers without a fraction, other values as decimals
+pub fn midgard_number(value: Option<&Bson>) -> String {
+    match value {
+        Some(Bson::Int32(v)) => v.to_string(),
+        Some(Bson::Int64(v)) => v.to_string(),
+        Some(amount @ Bson::Decimal128(_)) => Amount::from_bson(amount).unwrap_or_default().to_string(),
+        Some(other) => bson_to_f64(other).map(midgard_decimal).unwrap_or_else(|| "0".to_string()),
+        None => "0".to_string(),
+    }
+}
+
+/// Converts every number in `value` to a Midgard string, recursively
+pub fn midgard_strings(value: Value) -> Value {
+    match value {
+        Value::Number(number) => Value::String(match number.as_i64() {
+            Some(v) => v.to_string(),
+            None => midgard_decimal(number.as_f64().unwrap_or(0.0)),
+        }),
+        Value::Array(values) => Value::Array(values.into_iter().map(midgard_strings).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, v)| (key, midgard_strings(v))).collect()),
+        other => other,
+    }
+}
+
+fn midgard_decimal(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e17 {
+        format!("{:.0}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// `buckets` (in time order) with the stored ones where they exist and the others filled per `fill`.
+/// Stored buckets are matched by `_id`, so one that isn't in `buckets` is dropped instead of shadowing the rest.
+/// `opening` is the last interval before the first bucket, for carrying gauges forward.
+pub(crate) fn fill_buckets<H: HistorySeries>(buckets: &[(i64, i64)], stored: Vec<Document>, opening: Option<&Document>, fill: Fill) -> Vec<Document> {
+    let mut stored: HashMap<i64, Document> = stored.into_iter().map(|bucket| (get_i64(&bucket, "_id"), bucket)).collect();
+    let mut previous = opening.cloned();
+    let mut filled = Vec::with_capacity(buckets.len());
+
+    for &(start, end) in buckets {
+        let mut bucket = match stored.remove(&start) {
+            Some(found) => found,
+            None => {
+                let mut empty = doc! { "_id": start };
+                for metric in H::METRICS {
+                    let value = match (fill, metric.aggregation) {
+                        (Fill::Null, _) => Bson::Null,
+                        (_, Aggregation::SumByPool(_)) => Bson::Array(vec![]),
+                        (_, Aggregation::Sum) | (Fill::Zero, _) => Bson::Int64(0),
+                        (Fill::Previous, _) => previous.as_ref().and_then(|p| p.get(metric.name)).cloned().unwrap_or(Bson::Int64(0)),
+                    };
+                    empty.insert(metric.name, value);
+                }
+                empty
+            }
+        };
+        bucket.insert("startTime", start);
+        bucket.insert("endTime", end);
+        previous = Some(bucket.clone());
+        filled.push(bucket);
+    }
+    filled
+}
+
+/// Serves one Midgard `/v2/history/*` response for `pool` (depths) or the whole network
+async fn midgard_history<H: MidgardSeries, S: HistoryStore>(
+    state: &AppState<S>,
+    pool: Option<String>,
+    params: &MidgardParams,
+) -> Result<Json<Value>, ApiError> {
+    let tz = Tz::UTC;
+    let window = Window::resolve(params, tz, Utc::now().timestamp())?;
+
+    let (mut accumulators, breakdown) = rollups(H::METRICS, &[]);
+    accumulators.push(Accumulator::new("startTime", "startTime", AggregateOp::Min));
+    let query = AggregateQuery {
+        pool,
+        from: window.from,
+        to: window.to,
+        bucket: params.interval,
+        timezone: tz,
+        filter: None,
+        having: None,
+        accumulators,
+        breakdown,
+        sort: vec![("_id".to_string(), 1)],
+        after: None,
+        skip: 0,
+        limit: window.buckets.len() as u64,
+    };
+
+    let stored = state.store.aggregate(H::KIND, &query).await?;
+    let summary = summarize_range::<H, S>(state.store.as_ref(), &query, &[]).await?;
+    let buckets = fill_buckets::<H>(&window.buckets, stored, summary.previous.as_ref(), Fill::Previous);
+
+    let mut meta = H::midgard_meta(&summary);
+    meta.insert("startTime".to_string(), Value::String(window.from.to_string()));
+    meta.insert("endTime".to_string(), Value::String(window.to.to_string()));
+
+    let intervals: Vec<Value> = buckets
+        .iter()
+        .map(|bucket| {
+            let mut fields = H::midgard_fields(bucket);
+            fields.insert("startTime".to_string(), Value::String(get_i64(bucket, "startTime").to_string()));
+            fields.insert("endTime".to_string(), Value::String(get_i64(bucket, "endTime").to_string()));
+            Value::Object(fields)
+        })
+        .collect();
+
+    let mut response = Map::new();
+    response.insert("meta".to_string(), Value::Object(meta));
+    response.insert("intervals".to_string(), Value::Array(intervals));
+    Ok(Json(Value::Object(response)))
+}
+
+/// Handles GET /v2/history/depths/{pool}
+pub async fn get_midgard_depths<H: MidgardSeries, S: HistoryStore>(
+    State(state): State<AppState<S>>,
+    Path(pool): Path<String>,
+    params: MidgardParams,
+) -> Result<Json<Value>, ApiError> {
+    midgard_history::<H, S>(&state, Some(pool), &params).await
+}
+
+/// Handles GET /v2/history/earnings, /v2/history/swaps and /v2/history/runepool
+pub async fn get_midgard_history<H: MidgardSeries, S: HistoryStore>(
+    State(state): State<AppState<S>>,
+    params: MidgardParams,
+) -> Result<Json<Value>, ApiError> {
+    midgard_history::<H, S>(&state, None, &params).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::swaps_history::SwapsSeries;
+
+    const HOUR: i64 = 3600;
+    const DAY: i64 = 86_400;
+    /// 2024-01-10T12:30:00Z
+    const NOW: i64 = 1_704_889_800;
+
+    fn params(interval: Option<BucketUnit>, count: Option<usize>, from: Option<i64>, to: Option<i64>) -> MidgardParams {
+        MidgardParams { interval, count, from, to }
+    }
+
+    #[test]
+    fn resolve_without_interval_is_one_exact_bucket() {
+        let window = Window::resolve(&params(None, None, Some(100), Some(5000)), Tz::UTC, NOW).unwrap();
+        assert_eq!((window.from, window.to, window.buckets), (100, 5000, vec![(100, 5000)]));
+
+        let window = Window::resolve(&params(None, None, None, None), Tz::UTC, NOW).unwrap();
+        assert_eq!(window.buckets, vec![(0, NOW)]);
+    }
+
+    #[test]
+    fn resolve_rounds_from_and_to_to_bucket_boundaries() {
+        let from = NOW - 3 * DAY + 5 * HOUR;
+        let to = NOW - DAY;
+        let window = Window::resolve(&params(Some(BucketUnit::Day), None, Some(from), Some(to)), Tz::UTC, NOW).unwrap();
+        let midnight = NOW - NOW.rem_euclid(DAY);
+        assert_eq!(window.buckets, vec![(midnight - 3 * DAY, midnight - 2 * DAY), (midnight - 2 * DAY, midnight - DAY), (midnight - DAY, midnight)]);
+        assert_eq!((window.from, window.to), (midnight - 3 * DAY, midnight));
+    }
+
+    #[test]
+    fn resolve_with_count_ends_at_now_or_before_to() {
+        // The current hour is cut off at `now`
+        let window = Window::resolve(&params(Some(BucketUnit::Hour), Some(3), None, None), Tz::UTC, NOW).unwrap();
+        let hour = NOW - NOW.rem_euclid(HOUR);
+        assert_eq!(window.buckets, vec![(hour - 2 * HOUR, hour - HOUR), (hour - HOUR, hour), (hour, NOW)]);
+
+        // `to` is exclusive: a bucket starting at `to` isn't included
+        let window = Window::resolve(&params(Some(BucketUnit::Hour), Some(2), None, Some(hour)), Tz::UTC, NOW).unwrap();
+        assert_eq!(window.buckets, vec![(hour - 2 * HOUR, hour - HOUR), (hour - HOUR, hour)]);
+
+        // `from` and `count` walk forward
+        let window = Window::resolve(&params(Some(BucketUnit::Hour), Some(2), Some(hour - 10 * HOUR + 1), None), Tz::UTC, NOW).unwrap();
+        assert_eq!(window.buckets, vec![(hour - 10 * HOUR, hour - 9 * HOUR), (hour - 9 * HOUR, hour - 8 * HOUR)]);
+
+        // Only `interval`: the last `DEFAULT_COUNT` buckets
+        let window = Window::resolve(&params(Some(BucketUnit::Hour), None, None, None), Tz::UTC, NOW).unwrap();
+        assert_eq!(window.buckets.len(), DEFAULT_COUNT);
+    }
+
+    #[test]
+    fn resolve_follows_local_days_and_caps_the_range() {
+        // 2024-03-10 in New York has 23 hours
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let march_10 = 1_710_046_800;
+        let window = Window::resolve(&params(Some(BucketUnit::Day), Some(2), Some(march_10), None), tz, NOW + 100 * DAY).unwrap();
+        assert_eq!(window.buckets, vec![(march_10, march_10 + 23 * HOUR), (march_10 + 23 * HOUR, march_10 + 47 * HOUR)]);
+
+        let error = Window::resolve(&params(Some(BucketUnit::Hour), None, Some(NOW - 401 * HOUR), Some(NOW)), Tz::UTC, NOW);
+        assert!(error.is_err());
+        assert!(Window::resolve(&params(Some(BucketUnit::Hour), None, Some(NOW - 400 * HOUR), Some(NOW - NOW.rem_euclid(HOUR))), Tz::UTC, NOW).is_ok());
+    }
+
+    #[test]
+    fn fill_buckets_skips_stored_buckets_outside_the_list() {
+        let buckets = [(0, 3600), (3600, 7200), (7200, 10800)];
+        // `1800` isn't a bucket start; it must not hide the bucket at `3600`
+        let stored = vec![doc! { "_id": 1800_i64, "totalCount": 9_i64 }, doc! { "_id": 3600_i64, "totalCount": 5_i64 }];
+        let filled = fill_buckets::<SwapsSeries>(&buckets, stored, None, Fill::Zero);
+        let counts: Vec<i64> = filled.iter().map(|bucket| get_i64(bucket, "totalCount")).collect();
+        assert_eq!(counts, vec![0, 5, 0]);
+        assert_eq!(get_i64(&filled[2], "startTime"), 7200);
+        assert_eq!(get_i64(&filled[2], "endTime"), 10800);
+    }
+}
@@ -1,16 +1,49 @@
 use axum::{Router, routing::get};
 use std::sync::Arc;
-use mongodb::Database;
+
+use crate::db::store::HistoryStore;
 
 mod depth_history;
 mod earnings_history;
 mod swaps_history;
 mod runepool_history;
+pub mod cursor;
+pub mod filter;
+pub mod history;
+pub mod midgard_compat;
+pub mod query;
+
+use depth_history::DepthSeries;
+use earnings_history::EarningsSeries;
+use history::get_history;
+use midgard_compat::{get_midgard_depths, get_midgard_history};
+use runepool_history::RunePoolSeries;
+use swaps_history::SwapsSeries;
+
+/// State shared by the history handlers
+pub struct AppState<S> {
+    pub store: Arc<S>,
+    /// Largest `limit` a client may request
+    pub max_limit: usize,
+}
+
+// Manual impl: `S` itself doesn't need to be `Clone`
+impl<S> Clone for AppState<S> {
+    fn clone(&self) -> Self {
+        Self { store: Arc::clone(&self.store), max_limit: self.max_limit }
+    }
+}
 
-pub fn create_api_router(db: Arc<Database>) -> Router {
+pub fn create_api_router<S: HistoryStore>(state: AppState<S>) -> Router {
     Router::new()
-        .route("/api/depth-history", get(depth_history::get_depth_history).with_state(db.clone()))
-        .route("/api/earnings-history", get(earnings_history::get_earnings_history).with_state(db.clone()))
-        .route("/api/swaps-history", get(swaps_history::get_swaps_history).with_state(db.clone()))
-        .route("/api/rune-pool-history", get(runepool_history::get_rune_pool_history).with_state(db))
+        .route("/api/depth-history", get(get_history::<DepthSeries, S>))
+        .route("/api/earnings-history", get(get_history::<EarningsSeries, S>))
+        .route("/api/swaps-history", get(get_history::<SwapsSeries, S>))
+        .route("/api/rune-pool-history", get(get_history::<RunePoolSeries, S>))
+        // **Midgard-compatible** paths and response shapes
+        .route("/v2/history/depths/:pool", get(get_midgard_depths::<DepthSeries, S>))
+        .route("/v2/history/earnings", get(get_midgard_history::<EarningsSeries, S>))
+        .route("/v2/history/swaps", get(get_midgard_history::<SwapsSeries, S>))
+        .route("/v2/history/runepool", get(get_midgard_history::<RunePoolSeries, S>))
+        .with_state(state)
 }
@@ -0,0 +1,367 @@
+use axum::{async_trait, extract::{FromRequestParts, Query}, http::request::Parts};
+
+use chrono_tz::Tz;
+
+use crate::api::cursor::Cursor;
+use crate::api::filter::parse_filter;
+use crate::api::midgard_compat::MAX_COUNT;
+use crate::api::AppState;
+use crate::db::store::{BucketUnit, Filter, HistoryStore};
+use crate::error::{ApiError, ParameterError};
+
+/// Default page size when `limit` isn't given
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Deepest offset `page` may reach (`(page - 1) * limit`); deeper pages are reached with `cursor`
+pub const MAX_OFFSET: usize = 1_000_000;
+
+/// Earliest `from`/`to` accepted: 0001-01-01T00:00:00Z
+pub const MIN_TIMESTAMP: i64 = -62_135_596_800;
+/// Latest `from`/`to` accepted: 9999-12-31T23:59:59Z
+pub const MAX_TIMESTAMP: i64 = 253_402_300_799;
+
+/// How amounts are written in responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// Exact integer strings (default)
+    String,
+    /// JSON numbers, for chart clients that accept rounding beyond 2^53
+    Float,
+}
+
+/// How `fill` fills buckets without stored data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// Every metric `0`
+    Zero,
+    /// Flows `0`, gauges and averages carried forward from the previous bucket (Midgard's behaviour)
+    Previous,
+    /// Every metric `null`
+    Null,
+}
+
+/// Where `filters` is evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStage {
+    /// On the stored hourly intervals, before they are bucketed (default)
+    Intervals,
+    /// On the aggregated buckets, like SQL `HAVING`
+    Buckets,
+}
+
+/// Validated query parameters shared by all history endpoints.
+/// Extraction fails with a 400 listing every invalid parameter, not just the first one.
+#[derive(Debug, Clone)]
+pub struct HistoryParams {
+    /// Pool asset on per-pool endpoints; on network-wide ones, comma-separated pools to narrow
+    /// their per-pool breakdown to (earnings)
+    pub pool: Option<String>,
+    /// Bucket unit, `hour` by default
+    pub interval: BucketUnit,
+    /// IANA timezone (`tz`) whose calendar day/week/month/... buckets follow, UTC by default
+    pub timezone: Tz,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Exactly this many buckets, Midgard-style: the last ones up to `to` (or now), or the first ones from `from`.
+    /// Empty buckets are filled in; replaces paging.
+    pub count: Option<usize>,
+    /// `fill=zero|previous|null`: every bucket of the range, also those without data (`previous` with `count`)
+    pub fill: Option<Fill>,
+    /// 1-based page number
+    pub page: usize,
+    /// Keyset continuation from a previous response's `next`/`prev`; replaces `page`
+    pub cursor: Option<Cursor>,
+    /// Page size, between 1 and the configured maximum
+    pub limit: usize,
+    /// Sort keys in priority order (`sort_by`, or `sort` as older clients send it), e.g.
+    /// `totalVolume:desc,startTime:asc`; keys without a direction use `order`.
+    /// Checked against the series' fields by the handler.
+    pub sort: Vec<(String, i32)>,
+    /// `1` for ascending (default), `-1` for descending
+    pub order: i32,
+    /// `filters` expression (see `parse_filter`); repeated parameters are combined with AND.
+    /// Checked against the series' fields by the handler.
+    pub filter: Option<Filter>,
+    /// `filter_on=intervals|buckets`
+    pub filter_on: FilterStage,
+    /// `fields=startTime,totalVolume`: only these fields in each interval, all of them when absent.
+    /// Checked against the series' fields by the handler.
+    pub fields: Option<Vec<String>>,
+    /// `numbers=string|float`
+    pub numbers: NumberFormat,
+    /// `meta=false` leaves out the range summary and the bucket count, saving their passes over the range
+    pub meta: bool,
+}
+
+/// Collects violations while the raw parameters are parsed
+#[derive(Default)]
+pub(crate) struct Violations(Vec<ParameterError>);
+
+impl Violations {
+    pub(crate) fn push(&mut self, parameter: &str, message: impl Into<String>) {
+        self.0.push(ParameterError { parameter: parameter.to_string(), message: message.into() });
+    }
+
+    pub(crate) fn parse_i64(&mut self, parameter: &str, value: &str) -> Option<i64> {
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.push(parameter, format!("must be an integer, got `{}`", value));
+                None
+            }
+        }
+    }
+
+    /// Unix seconds between `MIN_TIMESTAMP` and `MAX_TIMESTAMP`, so calendar arithmetic on it can't overflow
+    pub(crate) fn parse_timestamp(&mut self, parameter: &str, value: &str) -> Option<i64> {
+        let timestamp = self.parse_i64(parameter, value)?;
+        if !(MIN_TIMESTAMP..=MAX_TIMESTAMP).contains(&timestamp) {
+            self.push(parameter, format!("must be a Unix timestamp between {} and {}, got {}", MIN_TIMESTAMP, MAX_TIMESTAMP, timestamp));
+            return None;
+        }
+        Some(timestamp)
+    }
+
+    pub(crate) fn parse_usize(&mut self, parameter: &str, value: &str) -> Option<usize> {
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.push(parameter, format!("must be a positive integer, got `{}`", value));
+                None
+            }
+        }
+    }
+
+    /// `value` if nothing was rejected, else a 400 listing every violation
+    pub(crate) fn finish<T>(self, value: T) -> Result<T, ApiError> {
+        if self.0.is_empty() {
+            Ok(value)
+        } else {
+            Err(ApiError::InvalidParameter(self.0))
+        }
+    }
+}
+
+impl HistoryParams {
+    /// Validates raw `key=value` pairs against the configured maximum page size
+    pub fn from_pairs(pairs: &[(String, String)], max_limit: usize) -> Result<Self, ApiError> {
+        let mut violations = Violations::default();
+        let mut params = HistoryParams {
+            pool: None,
+            interval: BucketUnit::Hour,
+            timezone: Tz::UTC,
+            from: None,
+            to: None,
+            count: None,
+            fill: None,
+            page: 1,
+            cursor: None,
+            limit: DEFAULT_LIMIT.min(max_limit),
+            sort: vec![],
+            order: 1,
+            filter: None,
+            filter_on: FilterStage::Intervals,
+            fields: None,
+            numbers: NumberFormat::String,
+            meta: true,
+        };
+
+        let mut sort_keys: Vec<(String, Option<i32>)> = vec![];
+        let mut filters = vec![];
+        for (key, value) in pairs {
+            match key.as_str() {
+                "pool" => params.pool = Some(value.trim().to_string()),
+                "interval" => match BucketUnit::parse(value.trim()) {
+                    Some(interval) => params.interval = interval,
+                    None => violations.push(
+                        "interval",
+                        format!("unknown interval `{}` (expected 5min, hour, day, week, month, quarter or year)", value),
+                    ),
+                },
+                "tz" => match value.trim().parse::<Tz>() {
+                    Ok(timezone) => params.timezone = timezone,
+                    Err(_) => violations.push("tz", format!("unknown timezone `{}` (expected an IANA name like `Europe/Berlin`)", value)),
+                },
+                "from" => params.from = violations.parse_timestamp("from", value),
+                "to" => params.to = violations.parse_timestamp("to", value),
+                "page" => match violations.parse_usize("page", value) {
+                    Some(0) => violations.push("page", "must be at least 1"),
+                    Some(page) => params.page = page,
+                    None => {}
+                },
+                "count" => match violations.parse_usize("count", value) {
+                    Some(count) if (1..=MAX_COUNT).contains(&count) => params.count = Some(count),
+                    Some(count) => violations.push("count", format!("must be between 1 and {}, got {}", MAX_COUNT, count)),
+                    None => {}
+                },
+                "fill" => match value.trim() {
+                    "zero" => params.fill = Some(Fill::Zero),
+                    "previous" => params.fill = Some(Fill::Previous),
+                    "null" => params.fill = Some(Fill::Null),
+                    other => violations.push("fill", format!("must be `zero`, `previous` or `null`, got `{}`", other)),
+                },
+                "cursor" => match Cursor::decode(value.trim()) {
+                    Some(cursor) => params.cursor = Some(cursor),
+                    None => violations.push("cursor", "is not a cursor returned by this API"),
+                },
+                "limit" => match violations.parse_usize("limit", value) {
+                    Some(limit) if limit == 0 || limit > max_limit => {
+                        violations.push("limit", format!("must be between 1 and {}, got {}", max_limit, limit))
+                    }
+                    Some(limit) => params.limit = limit,
+                    None => {}
+                },
+                "sort_by" | "sort" => {
+                    for key in value.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                        let (field, direction) = key.split_once(':').unwrap_or((key, ""));
+                        let direction = match direction.trim() {
+                            "" => None,
+                            "asc" => Some(1),
+                            "desc" => Some(-1),
+                            other => {
+                                violations.push("sort", format!("direction of `{}` must be `asc` or `desc`, got `{}`", field, other));
+                                continue;
+                            }
+                        };
+                        if sort_keys.iter().any(|(existing, _)| existing == field.trim()) {
+                            violations.push("sort", format!("`{}` is listed more than once", field.trim()));
+                            continue;
+                        }
+                        sort_keys.push((field.trim().to_string(), direction));
+                    }
+                }
+                "order" => match value.trim() {
+                    "asc" => params.order = 1,
+                    "desc" => params.order = -1,
+                    other => violations.push("order", format!("must be `asc` or `desc`, got `{}`", other)),
+                },
+                "filters" | "filter" => match parse_filter(value) {
+                    Ok(filter) => filters.push(filter),
+                    Err(e) => violations.push("filters", format!("`{}`: {}", value, e)),
+                },
+                "filter_on" => match value.trim() {
+                    "intervals" => params.filter_on = FilterStage::Intervals,
+                    "buckets" => params.filter_on = FilterStage::Buckets,
+                    other => violations.push("filter_on", format!("must be `intervals` or `buckets`, got `{}`", other)),
+                },
+                "fields" => {
+                    let fields = params.fields.get_or_insert_with(Vec::new);
+                    for field in value.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+                        if !fields.iter().any(|existing| existing == field) {
+                            fields.push(field.to_string());
+                        }
+                    }
+                    if fields.is_empty() {
+                        violations.push("fields", "must name at least one field");
+                    }
+                }
+                "numbers" => match value.trim() {
+                    "string" => params.numbers = NumberFormat::String,
+                    "float" => params.numbers = NumberFormat::Float,
+                    other => violations.push("numbers", format!("must be `string` or `float`, got `{}`", other)),
+                },
+                "meta" => match value.trim() {
+                    "true" => params.meta = true,
+                    "false" => params.meta = false,
+                    other => violations.push("meta", format!("must be `true` or `false`, got `{}`", other)),
+                },
+                _ => {}
+            }
+        }
+
+        // `order` may come after `sort` in the query string
+        params.filter = match filters.len() {
+            0 => None,
+            1 => filters.pop(),
+            _ => Some(Filter::All(filters)),
+        };
+        params.sort = sort_keys.into_iter().map(|(field, direction)| (field, direction.unwrap_or(params.order))).collect();
+
+        if let (Some(from), Some(to)) = (params.from, params.to) {
+            if from > to {
+                violations.push("from", format!("must not be after `to` ({} > {})", from, to));
+            }
+        }
+        match (params.page - 1).checked_mul(params.limit) {
+            Some(offset) if offset <= MAX_OFFSET => {}
+            _ => violations.push("page", format!("skips more than {} intervals; use `cursor` to go deeper", MAX_OFFSET)),
+        }
+        if params.cursor.is_some() && pairs.iter().any(|(key, _)| key == "page") {
+            violations.push("page", "can't be combined with `cursor`");
+        }
+        if params.fill.is_some() && params.count.is_none() {
+            // Filled buckets only exist in time order
+            for parameter in ["sort_by", "sort"] {
+                if pairs.iter().any(|(key, _)| key == parameter) {
+                    violations.push(parameter, "can't be combined with `fill`");
+                }
+            }
+            if params.filter_on == FilterStage::Buckets {
+                violations.push("filter_on", "`buckets` can't be combined with `fill`, which returns every bucket");
+            }
+        }
+        if params.count.is_some() {
+            if params.from.is_some() && params.to.is_some() {
+                violations.push("count", "only two of `from`, `to` and `count` may be given");
+            }
+            // `count` fixes the whole response: every bucket of the window, in time order
+            for parameter in ["page", "cursor", "limit", "sort_by", "sort"] {
+                if pairs.iter().any(|(key, _)| key == parameter) {
+                    violations.push(parameter, "can't be combined with `count`");
+                }
+            }
+            if params.filter_on == FilterStage::Buckets {
+                violations.push("filter_on", "`buckets` can't be combined with `count`, which returns every bucket");
+            }
+        }
+
+        violations.finish(params)
+    }
+
+    /// Pools named by `pool`, for endpoints that accept several
+    pub fn pools(&self) -> Vec<&str> {
+        self.pool.iter().flat_map(|pool| pool.split(',')).map(str::trim).filter(|pool| !pool.is_empty()).collect()
+    }
+
+    /// How many buckets to skip for the requested page, at most `MAX_OFFSET` once validated
+    pub fn skip(&self) -> u64 {
+        (self.page - 1).saturating_mul(self.limit) as u64
+    }
+}
+
+#[async_trait]
+impl<S: HistoryStore> FromRequestParts<AppState<S>> for HistoryParams {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState<S>) -> Result<Self, Self::Rejection> {
+        let Query(pairs) = Query::<Vec<(String, String)>>::from_request_parts(parts, state).await?;
+        HistoryParams::from_pairs(&pairs, state.max_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &[(&str, &str)]) -> Result<HistoryParams, Vec<String>> {
+        let pairs: Vec<(String, String)> = query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        HistoryParams::from_pairs(&pairs, 400).map_err(|e| match e {
+            ApiError::InvalidParameter(errors) => errors.into_iter().map(|error| error.parameter).collect(),
+            other => panic!("unexpected error {:?}", other),
+        })
+    }
+
+    #[test]
+    fn rejects_timestamps_outside_the_calendar() {
+        assert_eq!(parse(&[("to", "-9223372036854775808")]).unwrap_err(), vec!["to"]);
+        assert_eq!(parse(&[("from", "9223372036854775807"), ("to", "1")]).unwrap_err(), vec!["from"]);
+        assert!(parse(&[("from", "0"), ("to", &MAX_TIMESTAMP.to_string())]).is_ok());
+    }
+
+    #[test]
+    fn rejects_pages_beyond_the_maximum_offset() {
+        assert_eq!(parse(&[("page", "18446744073709551615"), ("limit", "400")]).unwrap_err(), vec!["page"]);
+        assert_eq!(parse(&[("page", "2502"), ("limit", "400")]).unwrap_err(), vec!["page"]);
+        assert_eq!(parse(&[("page", "2500"), ("limit", "400")]).unwrap().skip(), 999_600);
+    }
+}
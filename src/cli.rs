@@ -0,0 +1,159 @@
+use chrono::{DateTime, NaiveDate};
+
+use crate::db::models::HistoryKind;
+
+pub const USAGE: &str = "\
+Usage:
+  midgard-api [serve] [--no-scheduler] [--read-only]
+      Start the API server and the scheduled jobs (default). --no-scheduler serves the API
+      without the hourly ingestion and integrity checks; --read-only also never writes to the
+      store (no index creation), for API replicas next to a single ingestion worker.
+  midgard-api ingest [--once]
+      Run the hourly ingestion and integrity checks without the API. --once runs ingestion a
+      single time and exits, with status 1 if any collection failed (e.g. from cron).
+  midgard-api backfill --type <depth|earnings|swaps|runepool|all> --from <time> [--to <time>]
+                       [--pool <POOL,...>] [--concurrency <n>] [--restart]
+      Fetch and store a past range; rerun the same command to resume an interrupted run.
+      <time> is a Unix timestamp, a date (2024-01-31) or an RFC 3339 time. --to defaults to now,
+      or to the end of the interrupted run being resumed.
+      --pool applies to depth history and defaults to MIDGARD_POOLS (or every pool).
+  midgard-api verify [--repair]
+      Check every collection for gaps and overlaps once; --repair refetches the gaps.
+      Exits with status 1 if any issue was found.
+  midgard-api migrate
+      Create the store's indexes, copy legacy page documents (`depth_history`, ...) into the
+      interval collections with their watermarks, and exit.";
+
+/// What the binary was asked to do
+#[derive(Debug, Clone)]
+pub enum Command {
+    Serve(ServeArgs),
+    Ingest { once: bool },
+    Backfill(BackfillArgs),
+    Verify { repair: bool },
+    Migrate,
+    Help,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServeArgs {
+    /// Skip the hourly ingestion job and the integrity checks
+    pub no_scheduler: bool,
+    /// Never write to the store; implies `no_scheduler`
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillArgs {
+    pub kinds: Vec<HistoryKind>,
+    pub pools: Vec<String>,
+    pub from: i64,
+    /// `None` resumes an unfinished run's range, or loads up to now
+    pub to: Option<i64>,
+    pub concurrency: usize,
+    pub restart: bool,
+}
+
+impl Command {
+    /// Parses the arguments after the program name
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let rest = args.get(1..).unwrap_or_default();
+        match args.first().map(String::as_str) {
+            None => Ok(Command::Serve(ServeArgs::default())),
+            Some("serve") => {
+                let switches = switches("serve", rest, &["--no-scheduler", "--read-only"])?;
+                let read_only = switches.contains(&"--read-only");
+                Ok(Command::Serve(ServeArgs { no_scheduler: read_only || switches.contains(&"--no-scheduler"), read_only }))
+            }
+            Some("ingest") => Ok(Command::Ingest { once: switches("ingest", rest, &["--once"])?.contains(&"--once") }),
+            Some("backfill") => BackfillArgs::parse(rest).map(Command::Backfill),
+            Some("verify") => Ok(Command::Verify { repair: switches("verify", rest, &["--repair"])?.contains(&"--repair") }),
+            Some("migrate") => switches("migrate", rest, &[]).map(|_| Command::Migrate),
+            Some("help" | "--help" | "-h") => Ok(Command::Help),
+            // `midgard-api --read-only` is `serve --read-only`
+            Some(flag) if flag.starts_with("--") => Command::parse(&[&["serve".to_string()], args].concat()),
+            Some(other) => Err(format!("unknown command `{}`", other)),
+        }
+    }
+
+    /// Whether the command may write to the store (and so should make sure its indexes exist first)
+    pub fn writes(&self) -> bool {
+        !matches!(self, Command::Serve(ServeArgs { read_only: true, .. }) | Command::Help)
+    }
+}
+
+/// Checks that `args` only holds switches from `known`; returns the ones given
+fn switches<'a>(command: &str, args: &'a [String], known: &[&str]) -> Result<Vec<&'a str>, String> {
+    args.iter()
+        .map(|arg| match known.contains(&arg.as_str()) {
+            true => Ok(arg.as_str()),
+            false => Err(format!("unknown option `{}` for {}", arg, command)),
+        })
+        .collect()
+}
+
+impl BackfillArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut kinds = None;
+        let mut pools = vec![];
+        let mut from = None;
+        let mut to = None;
+        let mut concurrency = 1;
+        let mut restart = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            // `--flag value` or `--flag=value`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if flag == "--restart" {
+                restart = true;
+                continue;
+            }
+            let value = inline
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("`{}` needs a value", flag))?;
+            match flag {
+                "--type" => {
+                    kinds = Some(match value.as_str() {
+                        "all" => HistoryKind::ALL.to_vec(),
+                        name => vec![HistoryKind::parse(name).ok_or_else(|| format!("unknown --type `{}`", name))?],
+                    })
+                }
+                "--pool" => pools.extend(value.split(',').map(str::trim).filter(|pool| !pool.is_empty()).map(str::to_string)),
+                "--from" => from = Some(parse_time(&value)?),
+                "--to" => to = Some(parse_time(&value)?),
+                "--concurrency" => {
+                    concurrency = value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| format!("--concurrency must be a positive number, got `{}`", value))?
+                }
+                other => return Err(format!("unknown option `{}` for backfill", other)),
+            }
+        }
+
+        let kinds = kinds.ok_or("backfill needs --type")?;
+        let from = from.ok_or("backfill needs --from")?;
+        if let Some(to) = to.filter(|to| from >= *to) {
+            return Err(format!("--from ({}) must be before --to ({})", from, to));
+        }
+        Ok(BackfillArgs { kinds, pools, from, to, concurrency, restart })
+    }
+}
+
+/// Unix seconds, `YYYY-MM-DD` (midnight UTC) or RFC 3339
+fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp())
+        .map_err(|_| format!("`{}` is not a Unix timestamp, a date (2024-01-31) or an RFC 3339 time", value))
+}
@@ -1,10 +1,41 @@
 use dotenvy::dotenv;
 use std::env;
+use std::str::FromStr;
 
 #[allow(dead_code)]
+#[derive(Debug, Clone)]
 pub struct Settings {
+    /// `mongo` (default) or `memory`
+    pub store_backend: String,
+    /// Only required for the `mongo` backend
     pub mongo_uri: String,
     pub port: u16,
+    /// Largest page size (`limit`) the API accepts
+    pub api_max_limit: usize,
+    /// Midgard instances in priority order (without `/v2`); later ones are failovers
+    pub midgard_base_urls: Vec<String>,
+    /// A Midgard whose last aggregated block is older than this is considered stale
+    pub midgard_max_lag_secs: u64,
+    /// How long a failing or stale Midgard is skipped before it is tried again
+    pub midgard_unhealthy_cooldown_secs: u64,
+    /// Replay recorded Midgard responses from this directory instead of calling Midgard
+    pub midgard_fixtures_dir: Option<String>,
+    /// Pools to ingest depth history for. Empty means "discover from Midgard `/v2/pools`".
+    pub midgard_pools: Vec<String>,
+    /// How many times a failed Midgard request is retried before giving up
+    pub midgard_max_retries: u32,
+    /// First retry delay; doubled on every further attempt
+    pub midgard_backoff_base_ms: u64,
+    /// Upper bound for a single retry delay
+    pub midgard_backoff_max_ms: u64,
+    /// Maximum request rate towards Midgard
+    pub midgard_requests_per_second: f64,
+    /// Timeout for a single Midgard request
+    pub midgard_timeout_secs: u64,
+    /// How often stored history is checked for gaps and overlaps; `0` disables the check
+    pub integrity_check_interval_secs: u64,
+    /// Refetch the gaps the integrity check finds
+    pub integrity_auto_repair: bool,
 }
 
 impl Settings{
@@ -12,12 +43,57 @@ impl Settings{
 
         dotenv().ok(); // Load .env file
 
+        let store_backend = env::var("STORE_BACKEND").unwrap_or_else(|_| "mongo".to_string());
+        let mongo_uri = match store_backend.as_str() {
+            "memory" => env::var("MONGO_URI").unwrap_or_default(),
+            _ => env::var("MONGO_URI").expect("MONGO_URI to be set"),
+        };
+
         Self {
-            mongo_uri: env::var("MONGO_URI").expect("MONGO_URI to be set"),
+            store_backend,
+            mongo_uri,
             port : env::var("PORT")
             .unwrap_or_else(|_| "8080".to_string())
             .parse() //Converts the string "8080" to a u16 (integer).
-            .expect("PORT must be a valid number")            
+            .expect("PORT must be a valid number"),
+            api_max_limit: env_or("API_MAX_LIMIT", 400),
+            midgard_base_urls: env::var("MIDGARD_BASE_URLS")
+            .map(|urls| parse_list(&urls))
+            .ok()
+            .filter(|urls| !urls.is_empty())
+            .unwrap_or_else(|| vec!["https://midgard.ninerealms.com".to_string()]),
+            midgard_max_lag_secs: env_or("MIDGARD_MAX_LAG_SECS", 600),
+            midgard_unhealthy_cooldown_secs: env_or("MIDGARD_UNHEALTHY_COOLDOWN_SECS", 60),
+            midgard_fixtures_dir: env::var("MIDGARD_FIXTURES_DIR").ok(),
+            midgard_pools: env::var("MIDGARD_POOLS")
+            .map(|pools| parse_list(&pools))
+            .unwrap_or_default(), // e.g. "BTC.BTC,ETH.ETH,GAIA.ATOM"
+            midgard_max_retries: env_or("MIDGARD_MAX_RETRIES", 5),
+            midgard_backoff_base_ms: env_or("MIDGARD_BACKOFF_BASE_MS", 500),
+            midgard_backoff_max_ms: env_or("MIDGARD_BACKOFF_MAX_MS", 30_000),
+            midgard_requests_per_second: env_or("MIDGARD_REQUESTS_PER_SECOND", 2.0),
+            midgard_timeout_secs: env_or("MIDGARD_TIMEOUT_SECS", 30),
+            integrity_check_interval_secs: env_or("INTEGRITY_CHECK_INTERVAL_SECS", 86_400),
+            integrity_auto_repair: env_or("INTEGRITY_AUTO_REPAIR", false),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Splits a comma-separated env value, dropping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Reads and parses an optional env variable, panicking on malformed values like `PORT` does
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a valid {}", name, std::any::type_name::<T>())),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::AddAssign;
+
+use mongodb::bson::{Bson, Decimal128};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An exact RUNE or asset amount in base units (1e8 per coin).
+/// Midgard sends these as integer strings that can exceed `f64`'s exact range, so they are
+/// kept as integers end to end: stored as `Decimal128`, summed exactly, returned as strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(pub i128);
+
+impl Amount {
+    /// Reads any numeric BSON value (or numeric string); `None` for non-numbers
+    pub fn from_bson(value: &Bson) -> Option<Self> {
+        match value {
+            Bson::Int32(v) => Some(Amount(*v as i128)),
+            Bson::Int64(v) => Some(Amount(*v as i128)),
+            Bson::Double(v) if v.is_finite() => Some(Amount(v.round() as i128)),
+            Bson::Decimal128(v) => Self::parse(&v.to_string()),
+            Bson::String(s) => Self::parse(s),
+            _ => None,
+        }
+    }
+
+    /// Parses an integer or decimal string such as `"123"`, `"-5"`, `"12.0"` or `"1.05E+3"`.
+    /// Fractions of a base unit are truncated.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (mantissa, exponent) = match s.split_once(['E', 'e']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+            None => (s, 0),
+        };
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        let scale = exponent - fraction.len() as i32;
+        let value = if scale >= 0 {
+            digits.checked_mul(10_i128.checked_pow(scale as u32)?)?
+        } else {
+            10_i128.checked_pow(scale.unsigned_abs()).map(|divisor| digits / divisor).unwrap_or(0)
+        };
+        Some(Amount(if negative { -value } else { value }))
+    }
+
+    pub fn as_f64(self) -> f64 {
+        self.0 as f64
+    }
+
+    /// `Decimal128` holds 34 significant digits, far beyond any real supply
+    fn to_decimal128(self) -> Option<Decimal128> {
+        self.0.to_string().parse().ok()
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Amount(iter.map(|amount| amount.0).sum())
+    }
+}
+
+impl From<Amount> for Bson {
+    fn from(amount: Amount) -> Self {
+        match amount.to_decimal128() {
+            Some(decimal) => Bson::Decimal128(decimal),
+            None => Bson::Double(amount.as_f64()),
+        }
+    }
+}
+
+/// JSON (human-readable) gets a string, BSON a `Decimal128`
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.0.to_string());
+        }
+        match self.to_decimal128() {
+            Some(decimal) => decimal.serialize(serializer),
+            None => serializer.serialize_f64(self.as_f64()),
+        }
+    }
+}
+
+/// Accepts Midgard's strings (empty or `"null"` meaning zero), plain numbers and `Decimal128`
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Bson::deserialize(deserializer)? {
+            Bson::Null => Ok(Amount(0)),
+            Bson::String(s) if s.is_empty() || s == "null" => Ok(Amount(0)),
+            value => Amount::from_bson(&value).ok_or_else(|| serde::de::Error::custom(format!("Invalid amount: {}", value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson;
+
+    use super::*;
+
+    #[test]
+    fn parses_integer_and_decimal_strings() {
+        assert_eq!(Amount::parse("18014398509481987"), Some(Amount(18014398509481987)));
+        assert_eq!(Amount::parse(" -5 "), Some(Amount(-5)));
+        assert_eq!(Amount::parse("+12.0"), Some(Amount(12)));
+        assert_eq!(Amount::parse("1.05E+3"), Some(Amount(1050)));
+        assert_eq!(Amount::parse("7.9"), Some(Amount(7)));
+        assert_eq!(Amount::parse("12E-5"), Some(Amount(0)));
+        assert_eq!(Amount::parse(""), None);
+        assert_eq!(Amount::parse("."), None);
+        assert_eq!(Amount::parse("12a"), None);
+        assert_eq!(Amount::parse("1E99"), None);
+    }
+
+    #[test]
+    fn round_trips_through_decimal128_exactly() {
+        // 2^54 + 3 isn't representable as f64
+        let amount = Amount(18014398509481987);
+        let stored = Bson::from(amount);
+        assert!(matches!(stored, Bson::Decimal128(_)));
+        assert_eq!(Amount::from_bson(&stored), Some(amount));
+
+        #[derive(Serialize, Deserialize)]
+        struct Interval {
+            amount: Amount,
+        }
+        let raw = bson::to_raw_document_buf(&Interval { amount }).unwrap().to_document().unwrap();
+        assert!(matches!(raw.get("amount"), Some(Bson::Decimal128(_))));
+        let decoded: Interval = bson::from_document(raw).unwrap();
+        assert_eq!(decoded.amount, amount);
+    }
+
+    #[test]
+    fn json_uses_strings_and_accepts_midgard_values() {
+        assert_eq!(serde_json::to_string(&Amount(18014398509481987)).unwrap(), "\"18014398509481987\"");
+        for (json, expected) in [("\"42\"", 42), ("\"\"", 0), ("\"null\"", 0), ("null", 0), ("7", 7), ("2.0", 2)] {
+            assert_eq!(serde_json::from_str::<Amount>(json).unwrap(), Amount(expected), "{}", json);
+        }
+        assert!(serde_json::from_str::<Amount>("\"abc\"").is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use mongodb::bson::{Bson, Document};
+
+use crate::db::amount::Amount;
+use crate::db::models::{HistoryKind, IngestionState, IntegrityReport};
+use crate::db::store::{bson_to_f64, compare_bson, get_i64, AggregateOp, AggregateQuery, Breakdown, HistoryStore, StoreError};
+
+type IntervalKey = (Option<String>, i64);
+
+/// `HistoryStore` that keeps everything in process memory.
+/// Mirrors `MongoHistoryStore` semantics so the API can run without a MongoDB.
+#[derive(Debug, Default)]
+pub struct MemoryHistoryStore {
+    intervals: RwLock<HashMap<HistoryKind, BTreeMap<IntervalKey, Document>>>,
+    states: RwLock<HashMap<String, IngestionState>>,
+    reports: RwLock<HashMap<String, IntegrityReport>>,
+}
+
+impl MemoryHistoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intervals of `pool` sorted by `startTime`
+    fn pool_intervals(&self, kind: HistoryKind, pool: Option<&str>) -> Vec<Document> {
+        let intervals = self.intervals.read().unwrap();
+        intervals
+            .get(&kind)
+            .map(|stored| {
+                stored
+                    .iter()
+                    .filter(|((stored_pool, _), _)| stored_pool.as_deref() == pool)
+                    .map(|(_, interval)| interval.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// `query`'s intervals grouped into buckets, in bucket order
+    fn buckets(&self, kind: HistoryKind, query: &AggregateQuery) -> Vec<Document> {
+        let intervals: Vec<Document> = self
+            .pool_intervals(kind, query.pool.as_deref())
+            .into_iter()
+            .filter(|interval| {
+                let start_time = interval.get_i64("startTime").unwrap_or(i64::MIN);
+                let end_time = interval.get_i64("endTime").unwrap_or(i64::MAX);
+                start_time >= query.from && end_time <= query.to
+            })
+            .filter(|interval| query.filter.as_ref().is_none_or(|filter| filter.matches(interval)))
+            .collect();
+
+        // **Group by interval boundaries**
+        let mut buckets: BTreeMap<i64, Vec<&Document>> = BTreeMap::new();
+        for interval in &intervals {
+            let start_time = interval.get_i64("startTime").unwrap_or(0);
+            let bucket = match query.bucket {
+                Some(unit) => unit.bucket_start(query.timezone, start_time),
+                None => query.from,
+            };
+            buckets.entry(bucket).or_default().push(interval);
+        }
+
+        buckets
+            .into_iter()
+            .map(|(bucket, members)| {
+                let mut document = Document::new();
+                document.insert("_id", bucket);
+                for accumulator in &query.accumulators {
+                    document.insert(accumulator.output.as_str(), accumulate(accumulator.op, &accumulator.source, &members));
+                }
+                if let Some(breakdown) = &query.breakdown {
+                    document.insert(breakdown.field.as_str(), roll_up(breakdown, &members));
+                }
+                document
+            })
+            .filter(|bucket| query.having.as_ref().is_none_or(|having| having.matches(bucket)))
+            .collect()
+    }
+}
+
+/// One entry per key of `breakdown` over the members of one bucket, like the Mongo `$unwind`/`$group`
+fn roll_up(breakdown: &Breakdown, members: &[&Document]) -> Bson {
+    let mut by_key: BTreeMap<&str, Vec<&Document>> = BTreeMap::new();
+    for member in members {
+        let entries = member.get_array(&breakdown.field).map(|entries| entries.iter()).into_iter().flatten();
+        for entry in entries.filter_map(Bson::as_document) {
+            let Ok(key) = entry.get_str(&breakdown.key) else {
+                continue;
+            };
+            if breakdown.only.is_empty() || breakdown.only.iter().any(|only| only == key) {
+                by_key.entry(key).or_default().push(entry);
+            }
+        }
+    }
+
+    let entries = by_key.into_iter().map(|(key, entries)| {
+        let mut merged = Document::new();
+        merged.insert(breakdown.key.as_str(), key);
+        for sum in &breakdown.sums {
+            merged.insert(sum.as_str(), accumulate(AggregateOp::Sum, sum, &entries));
+        }
+        Bson::Document(merged)
+    });
+    Bson::Array(entries.collect())
+}
+
+/// Combines `source` over the members of one bucket like the matching Mongo accumulator
+fn accumulate(op: AggregateOp, source: &str, members: &[&Document]) -> Bson {
+    let values: Vec<&Bson> = members.iter().filter_map(|member| member.get(source)).collect();
+    match op {
+        AggregateOp::Sum => {
+            if values.iter().all(|v| matches!(v, Bson::Int32(_) | Bson::Int64(_))) {
+                Bson::Int64(values.iter().filter_map(|v| v.as_i64().or(v.as_i32().map(i64::from))).sum())
+            } else if values.iter().any(|v| matches!(v, Bson::Decimal128(_))) {
+                // Amounts: summed exactly, like `$sum` over `Decimal128`
+                Bson::from(values.iter().filter_map(|v| Amount::from_bson(v)).sum::<Amount>())
+            } else {
+                Bson::Double(values.iter().filter_map(|v| bson_to_f64(v)).sum())
+            }
+        }
+        AggregateOp::Avg => {
+            let numbers: Vec<f64> = values.iter().filter_map(|v| bson_to_f64(v)).collect();
+            if numbers.is_empty() {
+                Bson::Null
+            } else {
+                Bson::Double(numbers.iter().sum::<f64>() / numbers.len() as f64)
+            }
+        }
+        AggregateOp::Min => values
+            .iter()
+            .min_by(|a, b| compare_bson(Some(a), Some(b)))
+            .map(|v| (*v).clone())
+            .unwrap_or(Bson::Null),
+        AggregateOp::Max => values
+            .iter()
+            .max_by(|a, b| compare_bson(Some(a), Some(b)))
+            .map(|v| (*v).clone())
+            .unwrap_or(Bson::Null),
+        // Bucket members are in `startTime` order
+        AggregateOp::First => values.first().map(|v| (*v).clone()).unwrap_or(Bson::Null),
+        AggregateOp::Last => values.last().map(|v| (*v).clone()).unwrap_or(Bson::Null),
+        AggregateOp::TimeWeightedAvg => {
+            let (weighted, duration) = members
+                .iter()
+                .filter_map(|member| {
+                    let value = member.get(source).and_then(bson_to_f64)?;
+                    let duration = (get_i64(member, "endTime") - get_i64(member, "startTime")) as f64;
+                    Some((value * duration, duration))
+                })
+                .fold((0.0, 0.0), |(weighted, total), (value, duration)| (weighted + value, total + duration));
+            if duration > 0.0 {
+                Bson::Double(weighted / duration)
+            } else {
+                Bson::Null
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl HistoryStore for MemoryHistoryStore {
+    async fn migrate(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    async fn insert_intervals(&self, kind: HistoryKind, pool: Option<&str>, intervals: Vec<Document>) -> Result<usize, StoreError> {
+        let mut stored = self.intervals.write().unwrap();
+        let collection = stored.entry(kind).or_default();
+
+        let mut written = 0;
+        for mut interval in intervals {
+            let start_time = interval
+                .get_i64("startTime")
+                .map_err(|e| StoreError::Other(e.to_string()))?;
+            interval.insert("pool", pool);
+            collection.insert((pool.map(str::to_string), start_time), interval);
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    async fn aggregate(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<Vec<Document>, StoreError> {
+        let mut output = self.buckets(kind, query);
+
+        // **Keyset**: continue after the cursor's bucket, comparing the sort keys in order
+        if let Some(after) = &query.after {
+            output.retain(|bucket| {
+                query
+                    .sort
+                    .iter()
+                    .zip(after)
+                    .map(|((field, order), value)| {
+                        let ordering = compare_bson(bucket.get(field), Some(value));
+                        if *order < 0 { ordering.reverse() } else { ordering }
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .is_some_and(|ordering| ordering.is_gt())
+            });
+        }
+
+        // **Sorting**
+        if !query.sort.is_empty() {
+            output.sort_by(|a, b| {
+                query
+                    .sort
+                    .iter()
+                    .map(|(field, order)| {
+                        let ordering = compare_bson(a.get(field), b.get(field));
+                        if *order < 0 { ordering.reverse() } else { ordering }
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+
+        // **Pagination**
+        Ok(output
+            .into_iter()
+            .skip(query.skip as usize)
+            .take(query.limit as usize)
+            .collect())
+    }
+
+    async fn count_buckets(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<u64, StoreError> {
+        let accumulators = if query.having.is_some() { query.accumulators.clone() } else { vec![] };
+        Ok(self.buckets(kind, &AggregateQuery { accumulators, ..query.clone() }).len() as u64)
+    }
+    async fn latest_before(&self, kind: HistoryKind, pool: Option<&str>, before: i64) -> Result<Option<Document>, StoreError> {
+        Ok(self
+            .pool_intervals(kind, pool)
+            .into_iter()
+            .rev()
+            .find(|interval| interval.get_i64("startTime").is_ok_and(|start_time| start_time < before)))
+    }
+
+    async fn last_end_time(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Option<i64>, StoreError> {
+        Ok(self
+            .pool_intervals(kind, pool)
+            .iter()
+            .filter_map(|interval| interval.get_i64("endTime").ok())
+            .max())
+    }
+
+    async fn ingestion_state(&self, key: &str) -> Result<Option<IngestionState>, StoreError> {
+        Ok(self.states.read().unwrap().get(key).cloned())
+    }
+
+    async fn save_ingestion_state(&self, state: &IngestionState) -> Result<(), StoreError> {
+        self.states.write().unwrap().insert(state.id.clone(), state.clone());
+        Ok(())
+    }
+
+    async fn interval_spans(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Vec<(i64, i64)>, StoreError> {
+        Ok(self
+            .pool_intervals(kind, pool)
+            .iter()
+            .map(|interval| (get_i64(interval, "startTime"), get_i64(interval, "endTime")))
+            .collect())
+    }
+
+    async fn legacy_pages(&self, _kind: HistoryKind) -> Result<Vec<Document>, StoreError> {
+        // Nothing in process predates the interval layout
+        Ok(vec![])
+    }
+
+    async fn stored_pools(&self, kind: HistoryKind) -> Result<Vec<Option<String>>, StoreError> {
+        let intervals = self.intervals.read().unwrap();
+        let mut pools: Vec<Option<String>> = intervals
+            .get(&kind)
+            .map(|stored| stored.keys().map(|(pool, _)| pool.clone()).collect())
+            .unwrap_or_default();
+        pools.dedup();
+        Ok(pools)
+    }
+
+    async fn integrity_report(&self, key: &str) -> Result<Option<IntegrityReport>, StoreError> {
+        Ok(self.reports.read().unwrap().get(key).cloned())
+    }
+
+    async fn save_integrity_report(&self, report: &IntegrityReport) -> Result<(), StoreError> {
+        self.reports.write().unwrap().insert(report.id.clone(), report.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Tz;
+    use mongodb::bson::doc;
+
+    use super::*;
+    use crate::db::store::{Accumulator, BucketUnit};
+
+    fn earnings(start_time: i64, pools: &[(&str, i64)]) -> Document {
+        let pools: Vec<Document> = pools.iter().map(|(pool, earnings)| doc! { "pool": *pool, "earnings": Bson::from(Amount(*earnings as i128)) }).collect();
+        doc! { "startTime": start_time, "endTime": start_time + 3600, "earnings": Bson::from(Amount(1)), "pools": pools }
+    }
+
+    fn day_query(only: &[&str]) -> AggregateQuery {
+        AggregateQuery {
+            pool: None,
+            from: 0,
+            to: i64::MAX,
+            bucket: Some(BucketUnit::Day),
+            timezone: Tz::UTC,
+            filter: None,
+            having: None,
+            accumulators: vec![Accumulator::new("earnings", "earnings", AggregateOp::Sum)],
+            breakdown: Some(Breakdown {
+                field: "pools".into(),
+                key: "pool".into(),
+                sums: vec!["earnings".into()],
+                only: only.iter().map(|pool| pool.to_string()).collect(),
+            }),
+            sort: vec![],
+            after: None,
+            skip: 0,
+            limit: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn breakdown_sums_each_pool_once_per_bucket() {
+        let store = MemoryHistoryStore::new();
+        let intervals = vec![earnings(0, &[("BTC.BTC", 2), ("ETH.ETH", 3)]), earnings(3600, &[("ETH.ETH", 4), ("BTC.BTC", 5)])];
+        store.insert_intervals(HistoryKind::Earnings, None, intervals).await.unwrap();
+
+        let buckets = store.aggregate(HistoryKind::Earnings, &day_query(&[])).await.unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(Amount::from_bson(buckets[0].get("earnings").unwrap()), Some(Amount(2)));
+        let pools = buckets[0].get_array("pools").unwrap();
+        let expected = [("BTC.BTC", 7), ("ETH.ETH", 7)];
+        assert_eq!(pools.len(), expected.len());
+        for (entry, (pool, total)) in pools.iter().zip(expected) {
+            let entry = entry.as_document().unwrap();
+            assert_eq!(entry.get_str("pool").unwrap(), pool);
+            assert_eq!(Amount::from_bson(entry.get("earnings").unwrap()), Some(Amount(total)));
+        }
+
+        let buckets = store.aggregate(HistoryKind::Earnings, &day_query(&["ETH.ETH"])).await.unwrap();
+        let pools = buckets[0].get_array("pools").unwrap();
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].as_document().unwrap().get_str("pool").unwrap(), "ETH.ETH");
+    }
+}
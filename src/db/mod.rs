@@ -1,2 +1,6 @@
 pub mod mongo;
+pub mod amount;
 pub mod models;
+pub mod store;
+pub mod mongo_store;
+pub mod memory_store;
@@ -1,12 +1,13 @@
 use mongodb::bson::oid::ObjectId;
-use serde::{Deserialize, Serialize};
-use crate::utils::conversion::{deserialize_string_to_number,deserialize_string_to_number_i32,deserialize_string_to_number_i64};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use crate::db::amount::Amount;
+use crate::utils::conversion::{deserialize_string_to_number, deserialize_string_to_number_i64};
 
 /// Represents a single depth history interval
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DepthHistory {
-    #[serde(rename = "assetDepth", deserialize_with = "deserialize_string_to_number")]
-    pub asset_depth: f64,
+    #[serde(rename = "assetDepth")]
+    pub asset_depth: Amount,
 
     #[serde(rename = "assetPrice", deserialize_with = "deserialize_string_to_number")]
     pub asset_price: f64,
@@ -14,14 +15,14 @@ pub struct DepthHistory {
     #[serde(rename = "assetPriceUSD", deserialize_with = "deserialize_string_to_number")]
     pub asset_price_usd: f64,
 
-    #[serde(rename = "liquidityUnits", deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_units: f64,
+    #[serde(rename = "liquidityUnits")]
+    pub liquidity_units: Amount,
 
-    #[serde(rename = "membersCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub members_count: i32,  
+    #[serde(rename = "membersCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub members_count: i64,  
 
-    #[serde(rename = "runeDepth", deserialize_with = "deserialize_string_to_number")]
-    pub rune_depth: f64,
+    #[serde(rename = "runeDepth")]
+    pub rune_depth: Amount,
 
     #[serde(rename = "startTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub start_time: i64, 
@@ -29,14 +30,14 @@ pub struct DepthHistory {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64, 
 
-    #[serde(rename = "synthSupply", deserialize_with = "deserialize_string_to_number")]
-    pub synth_supply: f64,
+    #[serde(rename = "synthSupply")]
+    pub synth_supply: Amount,
 
-    #[serde(rename = "synthUnits", deserialize_with = "deserialize_string_to_number")]
-    pub synth_units: f64,
+    #[serde(rename = "synthUnits")]
+    pub synth_units: Amount,
 
-    #[serde(rename = "units", deserialize_with = "deserialize_string_to_number")]
-    pub total_units: f64,
+    #[serde(rename = "units")]
+    pub total_units: Amount,
 
     #[serde(rename = "luvi", deserialize_with = "deserialize_string_to_number")]
     pub luvi: f64,
@@ -45,20 +46,20 @@ pub struct DepthHistory {
 /// Represents metadata for depth history
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DepthHistoryMeta {
-    #[serde(rename = "endAssetDepth", deserialize_with = "deserialize_string_to_number")]
-    pub end_asset_depth: f64,
+    #[serde(rename = "endAssetDepth")]
+    pub end_asset_depth: Amount,
 
-    #[serde(rename = "endLPUnits", deserialize_with = "deserialize_string_to_number")]
-    pub end_lp_units: f64,
+    #[serde(rename = "endLPUnits")]
+    pub end_lp_units: Amount,
 
-    #[serde(rename = "endMemberCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub end_member_count: i32,  
+    #[serde(rename = "endMemberCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub end_member_count: i64,  
 
-    #[serde(rename = "endRuneDepth", deserialize_with = "deserialize_string_to_number")]
-    pub end_rune_depth: f64,
+    #[serde(rename = "endRuneDepth")]
+    pub end_rune_depth: Amount,
 
-    #[serde(rename = "endSynthUnits", deserialize_with = "deserialize_string_to_number")]
-    pub end_synth_units: f64,
+    #[serde(rename = "endSynthUnits")]
+    pub end_synth_units: Amount,
 
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64, 
@@ -69,25 +70,96 @@ pub struct DepthHistoryMeta {
     #[serde(rename = "priceShiftLoss", deserialize_with = "deserialize_string_to_number")]
     pub price_shift_loss: f64,
 
-    #[serde(rename = "startAssetDepth", deserialize_with = "deserialize_string_to_number")]
-    pub start_asset_depth: f64,
+    #[serde(rename = "startAssetDepth")]
+    pub start_asset_depth: Amount,
 
-    #[serde(rename = "startLPUnits", deserialize_with = "deserialize_string_to_number")]
-    pub start_lp_units: f64,
+    #[serde(rename = "startLPUnits")]
+    pub start_lp_units: Amount,
 
-    #[serde(rename = "startMemberCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub start_member_count: i32,  
+    #[serde(rename = "startMemberCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub start_member_count: i64,  
 
-    #[serde(rename = "startRuneDepth", deserialize_with = "deserialize_string_to_number")]
-    pub start_rune_depth: f64,
+    #[serde(rename = "startRuneDepth")]
+    pub start_rune_depth: Amount,
 
-    #[serde(rename = "startSynthUnits", deserialize_with = "deserialize_string_to_number")]
-    pub start_synth_units: f64,
+    #[serde(rename = "startSynthUnits")]
+    pub start_synth_units: Amount,
 
     #[serde(rename = "startTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub start_time: i64, 
 }
 
+/// Pool served when a depth history request doesn't name one
+pub const DEFAULT_POOL: &str = "BTC.BTC";
+
+/// The history types mirrored from Midgard.
+/// Each is stored one interval per document, keyed by `(pool, startTime)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryKind {
+    Depth,
+    Earnings,
+    Swaps,
+    RunePool,
+}
+
+impl HistoryKind {
+    pub const ALL: [HistoryKind; 4] = [HistoryKind::Depth, HistoryKind::Earnings, HistoryKind::Swaps, HistoryKind::RunePool];
+
+    /// Collection holding one document per stored interval
+    pub fn collection(self) -> &'static str {
+        match self {
+            HistoryKind::Depth => "depth_intervals",
+            HistoryKind::Earnings => "earnings_intervals",
+            HistoryKind::Swaps => "swaps_intervals",
+            HistoryKind::RunePool => "rune_pool_intervals",
+        }
+    }
+
+    /// Collection that held whole Midgard pages (`meta` plus `intervals`) before intervals were stored one by one
+    pub fn legacy_collection(self) -> &'static str {
+        match self {
+            HistoryKind::Depth => "depth_history",
+            HistoryKind::Earnings => "earnings_history",
+            HistoryKind::Swaps => "swaps_history",
+            HistoryKind::RunePool => "rune_pool_history",
+        }
+    }
+
+    /// Short name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            HistoryKind::Depth => "depth",
+            HistoryKind::Earnings => "earnings",
+            HistoryKind::Swaps => "swaps",
+            HistoryKind::RunePool => "runepool",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        HistoryKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Midgard `/v2/history/...` path for this type
+    pub fn endpoint(self, pool: Option<&str>) -> String {
+        match self {
+            HistoryKind::Depth => format!("depths/{}", pool.unwrap_or(DEFAULT_POOL)),
+            HistoryKind::Earnings => "earnings".to_string(),
+            HistoryKind::Swaps => "swaps".to_string(),
+            HistoryKind::RunePool => "runepool".to_string(),
+        }
+    }
+}
+
+/// A Midgard history response: aggregated `meta` plus the hourly `intervals`
+pub trait HistoryDocument: DeserializeOwned + Send {
+    type Interval: Serialize + DeserializeOwned + Send + Sync;
+
+    /// `meta.endTime`, where the next page starts
+    fn end_time(&self) -> i64;
+
+    fn into_intervals(self) -> Vec<Self::Interval>;
+}
+
 /// **New Structure: Stores `meta` and `intervals` in One Document**
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DepthHistoryDocument {
@@ -101,6 +173,18 @@ pub struct DepthHistoryDocument {
     pub intervals: Vec<DepthHistory>,
 }
 
+impl HistoryDocument for DepthHistoryDocument {
+    type Interval = DepthHistory;
+
+    fn end_time(&self) -> i64 {
+        self.meta.end_time
+    }
+
+    fn into_intervals(self) -> Vec<DepthHistory> {
+        self.intervals
+    }
+}
+
 
 /// Represents a single earnings history interval
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,20 +195,20 @@ pub struct EarningsHistory {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64,  
 
-    #[serde(rename = "liquidityFees", deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_fees: f64,
+    #[serde(rename = "liquidityFees")]
+    pub liquidity_fees: Amount,
 
-    #[serde(rename = "blockRewards", deserialize_with = "deserialize_string_to_number")]
-    pub block_rewards: f64,
+    #[serde(rename = "blockRewards")]
+    pub block_rewards: Amount,
 
-    #[serde(rename = "earnings", deserialize_with = "deserialize_string_to_number")]
-    pub earnings: f64,
+    #[serde(rename = "earnings")]
+    pub earnings: Amount,
 
-    #[serde(rename = "bondingEarnings", deserialize_with = "deserialize_string_to_number")]
-    pub bonding_earnings: f64,
+    #[serde(rename = "bondingEarnings")]
+    pub bonding_earnings: Amount,
 
-    #[serde(rename = "liquidityEarnings", deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_earnings: f64,
+    #[serde(rename = "liquidityEarnings")]
+    pub liquidity_earnings: Amount,
 
     #[serde(rename = "avgNodeCount", deserialize_with = "deserialize_string_to_number")]
     pub avg_node_count: f64,  
@@ -137,28 +221,28 @@ pub struct EarningsHistory {
 }
 
 /// Represents earnings per pool in an interval
-#[derive(Debug, Serialize, Deserialize,Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct EarningsPool {
     #[serde(rename = "pool")]
     pub pool: String,  // Pool name remains a `String`
 
-    #[serde(rename = "assetLiquidityFees", deserialize_with = "deserialize_string_to_number")]
-    pub asset_liquidity_fees: f64,
+    #[serde(rename = "assetLiquidityFees")]
+    pub asset_liquidity_fees: Amount,
 
-    #[serde(rename = "runeLiquidityFees", deserialize_with = "deserialize_string_to_number")]
-    pub rune_liquidity_fees: f64,
+    #[serde(rename = "runeLiquidityFees")]
+    pub rune_liquidity_fees: Amount,
 
-    #[serde(rename = "totalLiquidityFeesRune", deserialize_with = "deserialize_string_to_number")]
-    pub total_liquidity_fees_rune: f64,
+    #[serde(rename = "totalLiquidityFeesRune")]
+    pub total_liquidity_fees_rune: Amount,
 
-    #[serde(rename = "saverEarning", deserialize_with = "deserialize_string_to_number")]
-    pub saver_earning: f64,
+    #[serde(rename = "saverEarning")]
+    pub saver_earning: Amount,
 
-    #[serde(rename = "rewards", deserialize_with = "deserialize_string_to_number")]
-    pub rewards: f64,
+    #[serde(rename = "rewards")]
+    pub rewards: Amount,
 
-    #[serde(rename = "earnings", deserialize_with = "deserialize_string_to_number")]
-    pub earnings: f64,
+    #[serde(rename = "earnings")]
+    pub earnings: Amount,
 }
 
 /// Represents metadata for earnings history
@@ -170,20 +254,20 @@ pub struct EarningsHistoryMeta {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64,  
 
-    #[serde(rename = "liquidityFees", deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_fees: f64,
+    #[serde(rename = "liquidityFees")]
+    pub liquidity_fees: Amount,
 
-    #[serde(rename = "blockRewards", deserialize_with = "deserialize_string_to_number")]
-    pub block_rewards: f64,
+    #[serde(rename = "blockRewards")]
+    pub block_rewards: Amount,
 
-    #[serde(rename = "earnings", deserialize_with = "deserialize_string_to_number")]
-    pub earnings: f64,
+    #[serde(rename = "earnings")]
+    pub earnings: Amount,
 
-    #[serde(rename = "bondingEarnings", deserialize_with = "deserialize_string_to_number")]
-    pub bonding_earnings: f64,
+    #[serde(rename = "bondingEarnings")]
+    pub bonding_earnings: Amount,
 
-    #[serde(rename = "liquidityEarnings", deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_earnings: f64,
+    #[serde(rename = "liquidityEarnings")]
+    pub liquidity_earnings: Amount,
 
     #[serde(rename = "avgNodeCount", deserialize_with = "deserialize_string_to_number")]
     pub avg_node_count: f64,  
@@ -208,6 +292,18 @@ pub struct EarningsHistoryDocument {
     pub intervals: Vec<EarningsHistory>,
 }
 
+impl HistoryDocument for EarningsHistoryDocument {
+    type Interval = EarningsHistory;
+
+    fn end_time(&self) -> i64 {
+        self.meta.end_time
+    }
+
+    fn into_intervals(self) -> Vec<EarningsHistory> {
+        self.intervals
+    }
+}
+
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SwapsHistory {
@@ -217,59 +313,59 @@ pub struct SwapsHistory {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64,  
 
-    #[serde(rename = "toAssetCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_asset_count: i32,
+    #[serde(rename = "toAssetCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_asset_count: i64,
 
-    #[serde(rename = "toRuneCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_rune_count: i32,
+    #[serde(rename = "toRuneCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_rune_count: i64,
 
-    #[serde(rename = "toTradeCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_trade_count: i32,
+    #[serde(rename = "toTradeCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_trade_count: i64,
 
-    #[serde(rename = "fromTradeCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub from_trade_count: i32,
+    #[serde(rename = "fromTradeCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub from_trade_count: i64,
 
-    #[serde(rename = "toSecuredCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_secured_count: i32,
+    #[serde(rename = "toSecuredCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_secured_count: i64,
 
-    #[serde(rename = "fromSecuredCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub from_secured_count: i32,
+    #[serde(rename = "fromSecuredCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub from_secured_count: i64,
 
-    #[serde(rename = "synthMintCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub synth_mint_count: i32,
+    #[serde(rename = "synthMintCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub synth_mint_count: i64,
 
-    #[serde(rename = "synthRedeemCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub synth_redeem_count: i32,
+    #[serde(rename = "synthRedeemCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub synth_redeem_count: i64,
 
-    #[serde(rename = "totalCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub total_count: i32,
+    #[serde(rename = "totalCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub total_count: i64,
 
-    #[serde(rename = "toAssetVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_asset_volume: f64,
+    #[serde(rename = "toAssetVolume")]
+    pub to_asset_volume: Amount,
 
-    #[serde(rename = "toRuneVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_rune_volume: f64,
+    #[serde(rename = "toRuneVolume")]
+    pub to_rune_volume: Amount,
 
-    #[serde(rename = "toTradeVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_trade_volume: f64,
+    #[serde(rename = "toTradeVolume")]
+    pub to_trade_volume: Amount,
 
-    #[serde(rename = "fromTradeVolume", deserialize_with = "deserialize_string_to_number")]
-    pub from_trade_volume: f64,
+    #[serde(rename = "fromTradeVolume")]
+    pub from_trade_volume: Amount,
 
-    #[serde(rename = "toSecuredVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_secured_volume: f64,
+    #[serde(rename = "toSecuredVolume")]
+    pub to_secured_volume: Amount,
 
-    #[serde(rename = "fromSecuredVolume", deserialize_with = "deserialize_string_to_number")]
-    pub from_secured_volume: f64,
+    #[serde(rename = "fromSecuredVolume")]
+    pub from_secured_volume: Amount,
 
-    #[serde(rename = "synthMintVolume", deserialize_with = "deserialize_string_to_number")]
-    pub synth_mint_volume: f64,
+    #[serde(rename = "synthMintVolume")]
+    pub synth_mint_volume: Amount,
 
-    #[serde(rename = "synthRedeemVolume", deserialize_with = "deserialize_string_to_number")]
-    pub synth_redeem_volume: f64,
+    #[serde(rename = "synthRedeemVolume")]
+    pub synth_redeem_volume: Amount,
 
-    #[serde(rename = "totalVolume", deserialize_with = "deserialize_string_to_number")]
-    pub total_volume: f64,
+    #[serde(rename = "totalVolume")]
+    pub total_volume: Amount,
 
     #[serde(rename = "runePriceUSD", deserialize_with = "deserialize_string_to_number")]
     pub rune_price_usd: f64,
@@ -284,59 +380,59 @@ pub struct SwapsHistoryMeta {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64,
 
-    #[serde(rename = "toAssetCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_asset_count: i32,
+    #[serde(rename = "toAssetCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_asset_count: i64,
 
-    #[serde(rename = "toRuneCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_rune_count: i32,
+    #[serde(rename = "toRuneCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_rune_count: i64,
 
-    #[serde(rename = "toTradeCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_trade_count: i32,
+    #[serde(rename = "toTradeCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_trade_count: i64,
 
-    #[serde(rename = "fromTradeCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub from_trade_count: i32,
+    #[serde(rename = "fromTradeCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub from_trade_count: i64,
 
-    #[serde(rename = "toSecuredCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub to_secured_count: i32,
+    #[serde(rename = "toSecuredCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub to_secured_count: i64,
 
-    #[serde(rename = "fromSecuredCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub from_secured_count: i32,
+    #[serde(rename = "fromSecuredCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub from_secured_count: i64,
 
-    #[serde(rename = "synthMintCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub synth_mint_count: i32,
+    #[serde(rename = "synthMintCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub synth_mint_count: i64,
 
-    #[serde(rename = "synthRedeemCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub synth_redeem_count: i32,
+    #[serde(rename = "synthRedeemCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub synth_redeem_count: i64,
 
-    #[serde(rename = "totalCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub total_count: i32,
+    #[serde(rename = "totalCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub total_count: i64,
 
-    #[serde(rename = "toAssetVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_asset_volume: f64,
+    #[serde(rename = "toAssetVolume")]
+    pub to_asset_volume: Amount,
 
-    #[serde(rename = "toRuneVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_rune_volume: f64,
+    #[serde(rename = "toRuneVolume")]
+    pub to_rune_volume: Amount,
 
-    #[serde(rename = "toTradeVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_trade_volume: f64,
+    #[serde(rename = "toTradeVolume")]
+    pub to_trade_volume: Amount,
 
-    #[serde(rename = "fromTradeVolume", deserialize_with = "deserialize_string_to_number")]
-    pub from_trade_volume: f64,
+    #[serde(rename = "fromTradeVolume")]
+    pub from_trade_volume: Amount,
 
-    #[serde(rename = "toSecuredVolume", deserialize_with = "deserialize_string_to_number")]
-    pub to_secured_volume: f64,
+    #[serde(rename = "toSecuredVolume")]
+    pub to_secured_volume: Amount,
 
-    #[serde(rename = "fromSecuredVolume", deserialize_with = "deserialize_string_to_number")]
-    pub from_secured_volume: f64,
+    #[serde(rename = "fromSecuredVolume")]
+    pub from_secured_volume: Amount,
 
-    #[serde(rename = "synthMintVolume", deserialize_with = "deserialize_string_to_number")]
-    pub synth_mint_volume: f64,
+    #[serde(rename = "synthMintVolume")]
+    pub synth_mint_volume: Amount,
 
-    #[serde(rename = "synthRedeemVolume", deserialize_with = "deserialize_string_to_number")]
-    pub synth_redeem_volume: f64,
+    #[serde(rename = "synthRedeemVolume")]
+    pub synth_redeem_volume: Amount,
 
-    #[serde(rename = "totalVolume", deserialize_with = "deserialize_string_to_number")]
-    pub total_volume: f64,
+    #[serde(rename = "totalVolume")]
+    pub total_volume: Amount,
 
     #[serde(rename = "runePriceUSD", deserialize_with = "deserialize_string_to_number")]
     pub rune_price_usd: f64,
@@ -355,6 +451,18 @@ pub struct SwapsHistoryDocument {
     pub intervals: Vec<SwapsHistory>,
 }
 
+impl HistoryDocument for SwapsHistoryDocument {
+    type Interval = SwapsHistory;
+
+    fn end_time(&self) -> i64 {
+        self.meta.end_time
+    }
+
+    fn into_intervals(self) -> Vec<SwapsHistory> {
+        self.intervals
+    }
+}
+
 
 /// Represents a single RunePool history interval
 #[derive(Debug, Serialize, Deserialize)]
@@ -368,11 +476,11 @@ pub struct RunePoolHistory {
     // #[serde(rename = "depth", deserialize_with = "deserialize_string_to_number")]
     // pub depth: f64,
 
-    #[serde(rename = "count", deserialize_with = "deserialize_string_to_number_i32")]
-    pub count: i32,  
+    #[serde(rename = "count", deserialize_with = "deserialize_string_to_number_i64")]
+    pub count: i64,  
 
-    #[serde(rename = "units", deserialize_with = "deserialize_string_to_number")]
-    pub units: f64,
+    #[serde(rename = "units")]
+    pub units: Amount,
 }
 
 /// Represents metadata for RunePool history
@@ -384,17 +492,17 @@ pub struct RunePoolHistoryMeta {
     #[serde(rename = "endTime", deserialize_with = "deserialize_string_to_number_i64")]
     pub end_time: i64,  
 
-    #[serde(rename = "startUnits", deserialize_with = "deserialize_string_to_number")]
-    pub start_units: f64,
+    #[serde(rename = "startUnits")]
+    pub start_units: Amount,
 
-    #[serde(rename = "startCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub start_count: i32,  
+    #[serde(rename = "startCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub start_count: i64,  
 
-    #[serde(rename = "endUnits", deserialize_with = "deserialize_string_to_number")]
-    pub end_units: f64,
+    #[serde(rename = "endUnits")]
+    pub end_units: Amount,
 
-    #[serde(rename = "endCount", deserialize_with = "deserialize_string_to_number_i32")]
-    pub end_count: i32,  
+    #[serde(rename = "endCount", deserialize_with = "deserialize_string_to_number_i64")]
+    pub end_count: i64,  
 }
 
 /// **Final Structure: Stores `meta` and `intervals` in One Document**
@@ -409,3 +517,118 @@ pub struct RunePoolHistoryDocument {
     #[serde(rename = "intervals")]
     pub intervals: Vec<RunePoolHistory>,
 }
+
+impl HistoryDocument for RunePoolHistoryDocument {
+    type Interval = RunePoolHistory;
+
+    fn end_time(&self) -> i64 {
+        self.meta.end_time
+    }
+
+    fn into_intervals(self) -> Vec<RunePoolHistory> {
+        self.intervals
+    }
+}
+
+
+/// Ingestion watermark for one history collection (and pool, for depth history).
+/// Stored in the `ingestion_state` collection so every fetcher resumes where it left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestionState {
+    /// `collection` or `collection:pool`, e.g. `depth_intervals:ETH.ETH`
+    #[serde(rename = "_id")]
+    pub id: String,
+
+    #[serde(rename = "collection")]
+    pub collection: String,
+
+    #[serde(rename = "pool", skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
+    /// `meta.endTime` of the last page that was stored successfully
+    #[serde(rename = "lastEndTime")]
+    pub last_end_time: Option<i64>,
+
+    #[serde(rename = "lastAttempt")]
+    pub last_attempt: Option<i64>,
+
+    #[serde(rename = "lastSuccess")]
+    pub last_success: Option<i64>,
+
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+
+    /// End of the range a backfill loads, resolved when it first ran; unset for the hourly job
+    #[serde(rename = "targetEndTime", default, skip_serializing_if = "Option::is_none")]
+    pub target_end_time: Option<i64>,
+}
+
+impl IngestionState {
+    pub fn key(collection: &str, pool: Option<&str>) -> String {
+        match pool {
+            Some(pool) => format!("{}:{}", collection, pool),
+            None => collection.to_string(),
+        }
+    }
+}
+
+/// What an integrity check found wrong between stored intervals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+    /// Whole hours without a stored interval
+    Missing,
+    /// Two intervals with the same `startTime`
+    Duplicate,
+    /// An interval starting before the previous one ended
+    Overlap,
+    /// A gap shorter than an hour, or not on hour boundaries
+    Discontinuity,
+}
+
+/// One problem found by an integrity check, covering `startTime..endTime`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    #[serde(rename = "kind")]
+    pub kind: IssueKind,
+
+    #[serde(rename = "startTime")]
+    pub start_time: i64,
+
+    #[serde(rename = "endTime")]
+    pub end_time: i64,
+
+    /// Set once a refetch of the window was attempted
+    #[serde(rename = "repair", skip_serializing_if = "Option::is_none")]
+    pub repair: Option<String>,
+}
+
+/// Latest integrity check of one history collection (and pool, for depth history).
+/// Stored in the `integrity_reports` collection, keyed like `IngestionState`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    #[serde(rename = "_id")]
+    pub id: String,
+
+    #[serde(rename = "collection")]
+    pub collection: String,
+
+    #[serde(rename = "pool", skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
+    #[serde(rename = "checkedAt")]
+    pub checked_at: i64,
+
+    /// Stored intervals scanned, and the range they cover
+    #[serde(rename = "intervals")]
+    pub intervals: u64,
+
+    #[serde(rename = "firstStartTime")]
+    pub first_start_time: Option<i64>,
+
+    #[serde(rename = "lastEndTime")]
+    pub last_end_time: Option<i64>,
+
+    #[serde(rename = "issues")]
+    pub issues: Vec<IntegrityIssue>,
+}
@@ -1,6 +1,8 @@
-use  mongodb::{options::ClientOptions,Client};
+use  mongodb::{bson::{doc, Document}, options::{ClientOptions, IndexOptions},Client, Database, IndexModel};
 use tracing::info;
 
+use crate::db::models::HistoryKind;
+
 pub async fn connect_to_mongo(mongo_uri:&str)->Client{
     let client_options = ClientOptions::parse(mongo_uri)
     .await
@@ -11,4 +13,20 @@ pub async fn connect_to_mongo(mongo_uri:&str)->Client{
 
     info!("✅ Connected to MongoDB");
     client
-}
\ No newline at end of file
+}
+
+/// Creates the unique `(pool, startTime)` index on every interval collection,
+/// so an interval can only ever be stored once
+pub async fn ensure_indexes(db: &Database) -> mongodb::error::Result<()> {
+    for kind in HistoryKind::ALL {
+        let index = IndexModel::builder()
+            .keys(doc! { "pool": 1, "startTime": 1 })
+            .options(IndexOptions::builder().unique(true).name("pool_startTime".to_string()).build())
+            .build();
+
+        db.collection::<Document>(kind.collection()).create_index(index, None).await?;
+    }
+
+    info!("✅ Interval indexes are in place");
+    Ok(())
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error as MongoError, ErrorKind};
+use mongodb::options::{FindOneOptions, FindOptions, ReplaceOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+
+use crate::db::models::{HistoryKind, IngestionState, IntegrityReport};
+use crate::db::mongo::ensure_indexes;
+use crate::db::store::{bson_to_i64, get_i64, AggregateOp, AggregateQuery, Breakdown, BucketUnit, Comparison, Filter, HistoryStore, StoreError};
+
+/// `HistoryStore` backed by MongoDB: one collection per history type, one document per interval
+#[derive(Debug, Clone)]
+pub struct MongoHistoryStore {
+    db: Arc<Database>,
+}
+
+impl MongoHistoryStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+
+    fn intervals(&self, kind: HistoryKind) -> Collection<Document> {
+        self.db.collection(kind.collection())
+    }
+
+    fn states(&self) -> Collection<IngestionState> {
+        self.db.collection("ingestion_state")
+    }
+
+    fn reports(&self) -> Collection<IntegrityReport> {
+        self.db.collection("integrity_reports")
+    }
+}
+
+impl From<MongoError> for StoreError {
+    fn from(error: MongoError) -> Self {
+        match *error.kind {
+            ErrorKind::ServerSelection { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. } => StoreError::Unavailable(error.to_string()),
+            _ => StoreError::Other(error.to_string()),
+        }
+    }
+}
+
+/// Builds the aggregation pipeline for `query`
+fn aggregate_pipeline(query: &AggregateQuery) -> Vec<Document> {
+    let mut pipeline = bucket_stages(query);
+
+    // **Keyset**: continue after the cursor's bucket, comparing the sort keys in order
+    if let Some(after) = &query.after {
+        let branches: Vec<Document> = query
+            .sort
+            .iter()
+            .zip(after)
+            .enumerate()
+            .map(|(i, ((field, order), value))| {
+                let mut branch: Document = query.sort.iter().zip(after).take(i).map(|((field, _), value)| (field.clone(), value.clone())).collect();
+                let operator = if *order < 0 { "$lt" } else { "$gt" };
+                branch.insert(field.as_str(), doc! { operator: value.clone() });
+                branch
+            })
+            .collect();
+        pipeline.push(doc! { "$match": { "$or": branches } });
+    }
+
+    // **Sorting**
+    let mut sort = Document::new();
+    for (field, order) in &query.sort {
+        sort.insert(field.as_str(), *order);
+    }
+    if sort.is_empty() {
+        sort.insert("_id", 1);
+    }
+    pipeline.push(doc! { "$sort": sort });
+
+    // **Pagination**
+    pipeline.push(doc! { "$skip": query.skip as i64 });
+    pipeline.push(doc! { "$limit": query.limit as i64 });
+
+    pipeline
+}
+
+/// Selects the pool's intervals in the query's time range that pass its interval filter
+fn interval_stages(query: &AggregateQuery) -> Vec<Document> {
+    let mut pipeline = vec![];
+
+    // **Filter the pool's intervals based on `from` and `to` time range**
+    pipeline.push(doc! {
+        "$match": {
+            "pool": query.pool.as_deref(),
+            "startTime": { "$gte": query.from },
+            "endTime": { "$lte": query.to }
+        }
+    });
+
+    // **Apply the filter on the raw intervals**
+    if let Some(filter) = &query.filter {
+        pipeline.push(doc! { "$match": filter_document(filter) });
+    }
+
+    pipeline
+}
+
+/// Start of the bucket an interval belongs to, in Unix seconds
+fn bucket_id(query: &AggregateQuery) -> Bson {
+    let Some(bucket) = query.bucket else {
+        return Bson::Int64(query.from);
+    };
+    let (unit, bin_size) = match bucket {
+        BucketUnit::FiveMinutes => ("minute", 5),
+        BucketUnit::Hour => ("hour", 1),
+        BucketUnit::Day => ("day", 1),
+        BucketUnit::Week => ("week", 1),
+        BucketUnit::Month => ("month", 1),
+        BucketUnit::Quarter => ("quarter", 1),
+        BucketUnit::Year => ("year", 1),
+    };
+    // Bucket start in Unix seconds, truncated in the query's timezone (MongoDB 5.0+)
+    let bucket_start = doc! {
+        "$dateTrunc": {
+            "date": { "$toDate": { "$multiply": ["$startTime", 1000_i64] } },
+            "unit": unit,
+            "binSize": bin_size,
+            "timezone": query.timezone.name(),
+            "startOfWeek": "monday"
+        }
+    };
+    Bson::from(doc! { "$toLong": { "$divide": [{ "$toLong": bucket_start }, 1000] } })
+}
+
+/// Stages shared by every query: select the intervals and group them into buckets
+fn bucket_stages(query: &AggregateQuery) -> Vec<Document> {
+    let mut pipeline = interval_stages(query);
+
+    // ✅ `$first`/`$last` depend on document order, so feed `$group` in time order
+    if query.accumulators.iter().any(|accumulator| matches!(accumulator.op, AggregateOp::First | AggregateOp::Last)) {
+        pipeline.push(doc! { "$sort": { "startTime": 1 } });
+    }
+
+    // **Group by interval boundaries**
+    let bucket_id = bucket_id(query);
+    let mut group = doc! { "_id": bucket_id };
+    let mut weighted = vec![];
+    for accumulator in &query.accumulators {
+        let source = format!("${}", accumulator.source);
+        let operator = match accumulator.op {
+            AggregateOp::Sum => "$sum",
+            AggregateOp::Avg => "$avg",
+            AggregateOp::Min => "$min",
+            AggregateOp::Max => "$max",
+            AggregateOp::First => "$first",
+            AggregateOp::Last => "$last",
+            AggregateOp::TimeWeightedAvg => {
+                // Sum of value * duration and of the durations that had a value; divided below
+                let duration = doc! { "$subtract": ["$endTime", "$startTime"] };
+                let output = &accumulator.output;
+                group.insert(format!("{}__weighted", output), doc! {
+                    "$sum": { "$multiply": [&source, &duration] }
+                });
+                group.insert(format!("{}__duration", output), doc! {
+                    "$sum": { "$cond": [{ "$isNumber": &source }, &duration, 0] }
+                });
+                weighted.push(output.clone());
+                continue;
+            }
+        };
+        group.insert(accumulator.output.as_str(), doc! { operator: source });
+    }
+    pipeline.push(doc! { "$group": group });
+
+    if !weighted.is_empty() {
+        let mut averages = Document::new();
+        let mut helpers = vec![];
+        for output in &weighted {
+            let weighted_sum = format!("${}__weighted", output);
+            let duration = format!("${}__duration", output);
+            averages.insert(output.as_str(), doc! {
+                "$cond": [{ "$gt": [&duration, 0] }, { "$divide": [weighted_sum, &duration] }, null]
+            });
+            helpers.push(format!("{}__weighted", output));
+            helpers.push(format!("{}__duration", output));
+        }
+        pipeline.push(doc! { "$addFields": averages });
+        pipeline.push(doc! { "$unset": helpers });
+    }
+
+    // **Apply the filter on the aggregated buckets**
+    if let Some(having) = &query.having {
+        pipeline.push(doc! { "$match": filter_document(having) });
+    }
+
+    pipeline
+}
+
+/// Rolls up `breakdown` per bucket and key for the buckets starting at `ids`: every entry becomes a
+/// document of its own, entries are summed per `(bucket, key)`, then regrouped into one array per bucket
+fn breakdown_pipeline(query: &AggregateQuery, breakdown: &Breakdown, ids: &[i64]) -> Vec<Document> {
+    // Only the intervals of the returned buckets are unwound
+    let first = ids.iter().copied().min().unwrap_or(query.from);
+    let last = ids.iter().copied().max().unwrap_or(query.from);
+    let (from, to) = match query.bucket {
+        Some(unit) => (query.from.max(first), query.to.min(unit.next_bucket_start(query.timezone, last))),
+        None => (query.from, query.to),
+    };
+    let mut pipeline = interval_stages(&AggregateQuery { from, to, ..query.clone() });
+
+    let field = format!("${}", breakdown.field);
+    let key = format!("{}.{}", breakdown.field, breakdown.key);
+    pipeline.push(doc! { "$unwind": &field });
+    if !breakdown.only.is_empty() {
+        pipeline.push(doc! { "$match": { &key: { "$in": &breakdown.only } } });
+    }
+
+    let mut group = doc! { "_id": { "bucket": bucket_id(query), "key": format!("${}", key) } };
+    let mut entry = doc! { breakdown.key.as_str(): "$_id.key" };
+    for sum in &breakdown.sums {
+        group.insert(sum.as_str(), doc! { "$sum": format!("{}.{}", field, sum) });
+        entry.insert(sum.as_str(), format!("${}", sum));
+    }
+    pipeline.push(doc! { "$group": group });
+    pipeline.push(doc! { "$match": { "_id.bucket": { "$in": ids } } });
+    pipeline.push(doc! { "$sort": { "_id.key": 1 } });
+    pipeline.push(doc! { "$group": { "_id": "$_id.bucket", breakdown.field.as_str(): { "$push": entry } } });
+    pipeline
+}
+
+/// `$match` condition for `filter`; like `Filter::matches`, only numeric values match
+fn filter_document(filter: &Filter) -> Document {
+    match filter {
+        Filter::Compare { field, op, value } => {
+            let operator = match op {
+                Comparison::Eq => "$eq",
+                Comparison::Ne => "$ne",
+                Comparison::Gt => "$gt",
+                Comparison::Gte => "$gte",
+                Comparison::Lt => "$lt",
+                Comparison::Lte => "$lte",
+            };
+            doc! { field.as_str(): { operator: value, "$type": "number" } }
+        }
+        Filter::In { field, values } => doc! { field.as_str(): { "$in": values } },
+        Filter::All(filters) => doc! { "$and": filters.iter().map(filter_document).collect::<Vec<_>>() },
+        Filter::Any(filters) => doc! { "$or": filters.iter().map(filter_document).collect::<Vec<_>>() },
+    }
+}
+
+#[async_trait]
+impl HistoryStore for MongoHistoryStore {
+    async fn migrate(&self) -> Result<(), StoreError> {
+        Ok(ensure_indexes(&self.db).await?)
+    }
+
+    async fn insert_intervals(&self, kind: HistoryKind, pool: Option<&str>, intervals: Vec<Document>) -> Result<usize, StoreError> {
+        let collection = self.intervals(kind);
+        let upserts = intervals.into_iter().map(|mut interval| {
+            let collection = &collection;
+            async move {
+                let start_time = interval
+                    .get_i64("startTime")
+                    .map_err(|e| StoreError::Other(e.to_string()))?;
+                interval.insert("pool", pool);
+
+                let options = UpdateOptions::builder().upsert(true).build();
+                collection
+                    .update_one(doc! { "pool": pool, "startTime": start_time }, doc! { "$set": interval }, options)
+                    .await
+                    .map_err(StoreError::from)
+            }
+        });
+
+        try_join_all(upserts).await.map(|results| results.len())
+    }
+
+    async fn aggregate(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<Vec<Document>, StoreError> {
+        let cursor = self.intervals(kind).aggregate(aggregate_pipeline(query), None).await?;
+        let mut buckets: Vec<Document> = cursor.try_collect().await?;
+
+        // **Per-key breakdown** of the returned buckets only, merged in by bucket start
+        if let Some(breakdown) = query.breakdown.as_ref().filter(|_| !buckets.is_empty()) {
+            let ids: Vec<i64> = buckets.iter().map(|bucket| get_i64(bucket, "_id")).collect();
+            let cursor = self.intervals(kind).aggregate(breakdown_pipeline(query, breakdown, &ids), None).await?;
+            let mut entries: HashMap<i64, Bson> = cursor
+                .try_collect::<Vec<Document>>()
+                .await?
+                .into_iter()
+                .map(|mut rolled_up| (get_i64(&rolled_up, "_id"), rolled_up.remove(&breakdown.field).unwrap_or(Bson::Array(vec![]))))
+                .collect();
+            for bucket in &mut buckets {
+                let rolled_up = entries.remove(&get_i64(bucket, "_id")).unwrap_or(Bson::Array(vec![]));
+                bucket.insert(breakdown.field.as_str(), rolled_up);
+            }
+        }
+        Ok(buckets)
+    }
+
+    async fn count_buckets(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<u64, StoreError> {
+        // The bucket filter needs the aggregated values; otherwise grouping alone is enough to count
+        let accumulators = if query.having.is_some() { query.accumulators.clone() } else { vec![] };
+        let mut pipeline = bucket_stages(&AggregateQuery { accumulators, ..query.clone() });
+        pipeline.push(doc! { "$count": "total" });
+        let counted: Vec<Document> = self.intervals(kind).aggregate(pipeline, None).await?.try_collect().await?;
+        Ok(counted.first().and_then(|document| document.get("total")).and_then(bson_to_i64).unwrap_or(0) as u64)
+    }
+
+    async fn latest_before(&self, kind: HistoryKind, pool: Option<&str>, before: i64) -> Result<Option<Document>, StoreError> {
+        let options = FindOneOptions::builder().sort(doc! { "startTime": -1 }).build();
+        let filter = doc! { "pool": pool, "startTime": { "$lt": before } };
+        Ok(self.intervals(kind).find_one(filter, options).await?)
+    }
+
+    async fn last_end_time(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Option<i64>, StoreError> {
+        let options = FindOneOptions::builder().sort(doc! { "endTime": -1 }).build();
+        let latest = self.intervals(kind).find_one(doc! { "pool": pool }, options).await?;
+        Ok(latest.and_then(|document| match document.get("endTime") {
+            Some(Bson::Int64(end_time)) => Some(*end_time),
+            _ => None,
+        }))
+    }
+
+    async fn ingestion_state(&self, key: &str) -> Result<Option<IngestionState>, StoreError> {
+        Ok(self.states().find_one(doc! { "_id": key }, None).await?)
+    }
+
+    async fn save_ingestion_state(&self, state: &IngestionState) -> Result<(), StoreError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.states().replace_one(doc! { "_id": &state.id }, state, options).await?;
+        Ok(())
+    }
+
+    async fn interval_spans(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Vec<(i64, i64)>, StoreError> {
+        // Only the timestamps, walked along the `(pool, startTime)` index
+        let options = FindOptions::builder()
+            .sort(doc! { "startTime": 1 })
+            .projection(doc! { "_id": 0, "startTime": 1, "endTime": 1 })
+            .build();
+        let intervals: Vec<Document> = self.intervals(kind).find(doc! { "pool": pool }, options).await?.try_collect().await?;
+        Ok(intervals
+            .iter()
+            .map(|interval| (get_i64(interval, "startTime"), get_i64(interval, "endTime")))
+            .collect())
+    }
+
+    async fn legacy_pages(&self, kind: HistoryKind) -> Result<Vec<Document>, StoreError> {
+        let options = FindOptions::builder().sort(doc! { "meta.endTime": 1 }).build();
+        let legacy: Collection<Document> = self.db.collection(kind.legacy_collection());
+        Ok(legacy.find(None, options).await?.try_collect().await?)
+    }
+
+    async fn stored_pools(&self, kind: HistoryKind) -> Result<Vec<Option<String>>, StoreError> {
+        let pools = self.intervals(kind).distinct("pool", None, None).await?;
+        Ok(pools.into_iter().map(|pool| pool.as_str().map(str::to_string)).collect())
+    }
+
+    async fn integrity_report(&self, key: &str) -> Result<Option<IntegrityReport>, StoreError> {
+        Ok(self.reports().find_one(doc! { "_id": key }, None).await?)
+    }
+
+    async fn save_integrity_report(&self, report: &IntegrityReport) -> Result<(), StoreError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.reports().replace_one(doc! { "_id": &report.id }, report, options).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Tz;
+
+    use super::*;
+
+    #[test]
+    fn breakdown_unwinds_and_selects_pools_before_grouping() {
+        let query = AggregateQuery {
+            pool: None,
+            from: 0,
+            to: 86_400 * 10,
+            bucket: Some(BucketUnit::Day),
+            timezone: Tz::UTC,
+            filter: None,
+            having: None,
+            accumulators: vec![],
+            breakdown: None,
+            sort: vec![],
+            after: None,
+            skip: 0,
+            limit: 10,
+        };
+        let breakdown = Breakdown { field: "pools".into(), key: "pool".into(), sums: vec!["earnings".into()], only: vec!["ETH.ETH".into()] };
+        let pipeline = breakdown_pipeline(&query, &breakdown, &[86_400]);
+        let stages: Vec<&str> = pipeline.iter().filter_map(|stage| stage.keys().next().map(String::as_str)).collect();
+        assert_eq!(stages, vec!["$match", "$unwind", "$match", "$group", "$match", "$sort", "$group"]);
+
+        // Only the returned bucket's intervals are read
+        let range = pipeline[0].get_document("$match").unwrap();
+        assert_eq!(range.get_document("startTime").unwrap().get_i64("$gte").unwrap(), 86_400);
+        assert_eq!(range.get_document("endTime").unwrap().get_i64("$lte").unwrap(), 2 * 86_400);
+        assert_eq!(pipeline[2], doc! { "$match": { "pools.pool": { "$in": ["ETH.ETH"] } } });
+    }
+}
@@ -0,0 +1,385 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use async_trait::async_trait;
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use mongodb::bson::{Bson, Document};
+
+use crate::db::amount::Amount;
+use crate::db::models::{HistoryKind, IngestionState, IntegrityReport};
+
+/// Error returned by a `HistoryStore`
+#[derive(Debug)]
+pub enum StoreError {
+    /// The backend can't be reached right now (connection refused, no server selected, ...)
+    Unavailable(String),
+    /// Anything else: bad documents, failed commands, ...
+    Other(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Unavailable(e) => write!(f, "storage unavailable: {}", e),
+            StoreError::Other(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// How an accumulator combines the values of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    /// Value of the earliest interval in the bucket (by `startTime`)
+    First,
+    /// Value of the latest interval in the bucket (by `startTime`)
+    Last,
+    /// Mean weighted by each interval's duration (`endTime - startTime`)
+    TimeWeightedAvg,
+}
+
+/// One output field of an aggregated bucket: `output = op(source)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    pub output: String,
+    pub source: String,
+    pub op: AggregateOp,
+}
+
+impl Accumulator {
+    pub fn new(output: &str, source: &str, op: AggregateOp) -> Self {
+        Self { output: output.to_string(), source: source.to_string(), op }
+    }
+}
+
+/// Rolls up an array of entries per key inside each bucket, e.g. earnings per pool.
+/// The bucket's `field` holds one entry per distinct `key` value, ordered by key, with every field
+/// in `sums` added up over the bucket's intervals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakdown {
+    /// Array of entries in each interval; also the output field
+    pub field: String,
+    /// Entry field the entries are merged by
+    pub key: String,
+    /// Entry fields summed per key
+    pub sums: Vec<String>,
+    /// Only entries whose key is one of these; every entry when empty
+    pub only: Vec<String>,
+}
+
+/// Comparison operator of a `Filter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+/// Condition on the numeric fields of an interval or bucket
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// `field <op> value`
+    Compare { field: String, op: Comparison, value: Bson },
+    /// `field` equals one of `values`
+    In { field: String, values: Vec<Bson> },
+    All(Vec<Filter>),
+    Any(Vec<Filter>),
+}
+
+impl Filter {
+    /// Every field the filter reads
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Filter::Compare { field, .. } | Filter::In { field, .. } => vec![field.as_str()],
+            Filter::All(filters) | Filter::Any(filters) => filters.iter().flat_map(Filter::fields).collect(),
+        }
+    }
+
+    /// Evaluates the filter like MongoDB would; missing fields never match
+    pub fn matches(&self, document: &Document) -> bool {
+        match self {
+            Filter::Compare { field, op, value } => {
+                let Some(actual) = document.get(field).filter(|actual| bson_to_f64(actual).is_some()) else {
+                    return false;
+                };
+                let ordering = compare_bson(Some(actual), Some(value));
+                match op {
+                    Comparison::Eq => ordering.is_eq(),
+                    Comparison::Ne => ordering.is_ne(),
+                    Comparison::Gt => ordering.is_gt(),
+                    Comparison::Gte => ordering.is_ge(),
+                    Comparison::Lt => ordering.is_lt(),
+                    Comparison::Lte => ordering.is_le(),
+                }
+            }
+            Filter::In { field, values } => {
+                let actual = document.get(field).filter(|actual| bson_to_f64(actual).is_some());
+                actual.is_some() && values.iter().any(|value| compare_bson(actual, Some(value)).is_eq())
+            }
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(document)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(document)),
+        }
+    }
+}
+
+/// Width of an aggregation bucket. Days and larger follow the calendar of the query's
+/// timezone: weeks start on Monday, months/quarters/years on their first day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketUnit {
+    FiveMinutes,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl BucketUnit {
+    /// Parses Midgard's `interval` names
+    pub fn parse(interval: &str) -> Option<Self> {
+        match interval {
+            "5min" => Some(BucketUnit::FiveMinutes),
+            "hour" => Some(BucketUnit::Hour),
+            "day" => Some(BucketUnit::Day),
+            "week" => Some(BucketUnit::Week),
+            "month" => Some(BucketUnit::Month),
+            "quarter" => Some(BucketUnit::Quarter),
+            "year" => Some(BucketUnit::Year),
+            _ => None,
+        }
+    }
+
+    /// Start (Unix seconds) of the bucket containing `timestamp` in `tz`
+    pub fn bucket_start(self, tz: Tz, timestamp: i64) -> i64 {
+        let Some(local) = tz.timestamp_opt(timestamp, 0).single() else {
+            return timestamp;
+        };
+        // ✅ Sub-day buckets are cut on absolute time shifted by the instant's own UTC offset,
+        // so the repeated hour of a DST fall-back stays a bucket of its own (like `$dateTrunc`)
+        let offset = i64::from(local.offset().fix().local_minus_utc());
+        let date = local.date_naive();
+        let truncated: NaiveDateTime = match self {
+            BucketUnit::FiveMinutes => return truncate_shifted(timestamp, offset, 300),
+            BucketUnit::Hour => return truncate_shifted(timestamp, offset, 3600),
+            BucketUnit::Day => date.and_hms_opt(0, 0, 0).unwrap(),
+            BucketUnit::Week => match date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64)) {
+                Some(monday) => monday.and_hms_opt(0, 0, 0).unwrap(),
+                None => return timestamp,
+            },
+            BucketUnit::Month => first_day(date.year(), date.month()),
+            BucketUnit::Quarter => first_day(date.year(), (date.month() - 1) / 3 * 3 + 1),
+            BucketUnit::Year => first_day(date.year(), 1),
+        };
+        resolve_local(tz, truncated).unwrap_or(timestamp)
+    }
+
+    /// Start of the bucket following the one that starts at `start`
+    pub fn next_bucket_start(self, tz: Tz, start: i64) -> i64 {
+        let Some(local) = tz.timestamp_opt(start, 0).single() else {
+            return i64::MAX;
+        };
+        let local = local.naive_local();
+        let next = match self {
+            BucketUnit::FiveMinutes => return start.saturating_add(300),
+            BucketUnit::Hour => return start.saturating_add(3600),
+            BucketUnit::Day => local.checked_add_days(Days::new(1)),
+            BucketUnit::Week => local.checked_add_days(Days::new(7)),
+            BucketUnit::Month => local.checked_add_months(Months::new(1)),
+            BucketUnit::Quarter => local.checked_add_months(Months::new(3)),
+            BucketUnit::Year => local.checked_add_months(Months::new(12)),
+        };
+        // Past the end of the calendar there is no next bucket
+        next.and_then(|next| resolve_local(tz, next)).unwrap_or(i64::MAX)
+    }
+}
+
+/// `timestamp` rounded down to a multiple of `width` seconds in a clock `offset` seconds ahead of UTC
+fn truncate_shifted(timestamp: i64, offset: i64, width: i64) -> i64 {
+    let shifted = timestamp.saturating_add(offset);
+    (shifted - shifted.rem_euclid(width)).saturating_sub(offset)
+}
+
+/// Converts a local time to Unix seconds.
+/// ✅ A local midnight skipped by a DST change starts at the first instant that exists
+fn resolve_local(tz: Tz, local: NaiveDateTime) -> Option<i64> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&local.checked_add_signed(chrono::Duration::hours(1))?).earliest())
+        .map(|start| start.timestamp())
+}
+
+fn first_day(year: i32, month: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+}
+
+/// Groups the stored intervals of one pool into calendar buckets.
+/// Every output document has `_id` set to the bucket start plus one field per accumulator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateQuery {
+    pub pool: Option<String>,
+    /// Only intervals with `startTime >= from` and `endTime <= to` are used
+    pub from: i64,
+    pub to: i64,
+    /// `None` groups the whole range into a single bucket with `_id` = `from`
+    pub bucket: Option<BucketUnit>,
+    /// Timezone whose calendar the buckets follow
+    pub timezone: Tz,
+    /// Applied to the raw intervals before they are grouped
+    pub filter: Option<Filter>,
+    /// Applied to the aggregated buckets (like SQL `HAVING`)
+    pub having: Option<Filter>,
+    pub accumulators: Vec<Accumulator>,
+    /// Per-key rollup of an array field, merged into the buckets the query returns
+    pub breakdown: Option<Breakdown>,
+    /// `(output field, 1 | -1)`; defaults to the bucket start ascending
+    pub sort: Vec<(String, i32)>,
+    /// Keyset: only buckets that sort strictly after these values, one per `sort` key
+    pub after: Option<Vec<Bson>>,
+    pub skip: u64,
+    pub limit: u64,
+}
+
+/// Storage for the hourly history intervals and the ingestion bookkeeping.
+/// `MongoHistoryStore` is the production backend, `MemoryHistoryStore` keeps everything in process.
+#[async_trait]
+pub trait HistoryStore: Send + Sync + 'static {
+    /// Creates whatever the backend needs before ingestion, such as the unique interval indexes
+    async fn migrate(&self) -> Result<(), StoreError>;
+
+    /// Upserts intervals keyed by `(pool, startTime)`; returns how many were written
+    async fn insert_intervals(&self, kind: HistoryKind, pool: Option<&str>, intervals: Vec<Document>) -> Result<usize, StoreError>;
+
+    /// Buckets intervals as described by `query`
+    async fn aggregate(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<Vec<Document>, StoreError>;
+
+    /// Number of buckets `query` yields, ignoring its keyset and paging
+    async fn count_buckets(&self, kind: HistoryKind, query: &AggregateQuery) -> Result<u64, StoreError>;
+
+    /// Latest stored interval of `pool` that starts before `before`
+    async fn latest_before(&self, kind: HistoryKind, pool: Option<&str>, before: i64) -> Result<Option<Document>, StoreError>;
+
+    /// `endTime` of the latest stored interval of `pool`
+    async fn last_end_time(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Option<i64>, StoreError>;
+
+    async fn ingestion_state(&self, key: &str) -> Result<Option<IngestionState>, StoreError>;
+
+    async fn save_ingestion_state(&self, state: &IngestionState) -> Result<(), StoreError>;
+
+    /// `(startTime, endTime)` of every stored interval of `pool`, ordered by `startTime`
+    async fn interval_spans(&self, kind: HistoryKind, pool: Option<&str>) -> Result<Vec<(i64, i64)>, StoreError>;
+
+    /// Pages stored in `kind`'s legacy collection, for `migrate`; empty when there is none
+    async fn legacy_pages(&self, kind: HistoryKind) -> Result<Vec<Document>, StoreError>;
+
+    /// Every `pool` value with stored intervals (`None` for the network-wide types)
+    async fn stored_pools(&self, kind: HistoryKind) -> Result<Vec<Option<String>>, StoreError>;
+
+    async fn integrity_report(&self, key: &str) -> Result<Option<IntegrityReport>, StoreError>;
+
+    async fn save_integrity_report(&self, report: &IntegrityReport) -> Result<(), StoreError>;
+}
+
+/// Reads any numeric BSON value as `f64`
+pub fn bson_to_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(v) => Some(*v),
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Decimal128(v) => v.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Reads any integral BSON value as `i64` (doubles are truncated)
+pub fn bson_to_i64(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(v) => Some(*v as i64),
+        Bson::Int64(v) => Some(*v),
+        other => bson_to_f64(other).map(|v| v as i64),
+    }
+}
+
+/// Numeric field of an aggregated document, `0.0` when missing
+pub fn get_f64(document: &Document, key: &str) -> f64 {
+    document.get(key).and_then(bson_to_f64).unwrap_or(0.0)
+}
+
+/// Exact amount field of an aggregated document, zero when missing
+pub fn get_amount(document: &Document, key: &str) -> Amount {
+    document.get(key).and_then(Amount::from_bson).unwrap_or_default()
+}
+
+/// Integral field of an aggregated document, `0` when missing
+pub fn get_i64(document: &Document, key: &str) -> i64 {
+    document.get(key).and_then(bson_to_i64).unwrap_or(0)
+}
+
+/// Orders BSON values the way a `$sort` on numeric fields does; non-numbers sort first
+pub fn compare_bson(a: Option<&Bson>, b: Option<&Bson>) -> Ordering {
+    // Integers and decimals compare exactly; `f64` would tie amounts beyond 2^53
+    let exact = |value: Option<&Bson>| match value {
+        Some(value @ (Bson::Int32(_) | Bson::Int64(_) | Bson::Decimal128(_))) => Amount::from_bson(value),
+        _ => None,
+    };
+    if let (Some(a), Some(b)) = (exact(a), exact(b)) {
+        return a.cmp(&b);
+    }
+    let a = a.and_then(bson_to_f64);
+    let b = b.and_then(bson_to_f64);
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_YORK: Tz = chrono_tz::America::New_York;
+
+    #[test]
+    fn fall_back_keeps_both_one_oclock_hours() {
+        // 2024-11-03 01:00 EDT and 01:00 EST
+        let (first, second) = (1730610000, 1730613600);
+        assert_eq!(BucketUnit::Hour.bucket_start(NEW_YORK, first + 1800), first);
+        assert_eq!(BucketUnit::Hour.bucket_start(NEW_YORK, second), second);
+        assert_eq!(BucketUnit::Hour.bucket_start(NEW_YORK, second + 1800), second);
+        assert_eq!(BucketUnit::Hour.next_bucket_start(NEW_YORK, first), second);
+    }
+
+    #[test]
+    fn days_follow_the_local_calendar_across_dst() {
+        // Fall-back day has 25 hours, spring-forward day 23
+        assert_eq!(BucketUnit::Day.bucket_start(NEW_YORK, 1730613600), 1730606400);
+        assert_eq!(BucketUnit::Day.next_bucket_start(NEW_YORK, 1730606400), 1730696400);
+        assert_eq!(BucketUnit::Day.bucket_start(NEW_YORK, 1710050000), 1710046800);
+        assert_eq!(BucketUnit::Day.next_bucket_start(NEW_YORK, 1710046800), 1710129600);
+        assert_eq!(BucketUnit::Month.bucket_start(NEW_YORK, 1730613600), 1730433600);
+        assert_eq!(BucketUnit::Month.next_bucket_start(NEW_YORK, 1730433600), 1733029200);
+    }
+
+    #[test]
+    fn sub_day_buckets_follow_fractional_offsets() {
+        // Kolkata is UTC+05:30: 03:43:20 local falls in the 03:00 hour and the 03:40 five minutes
+        let kolkata = chrono_tz::Asia::Kolkata;
+        assert_eq!(BucketUnit::Hour.bucket_start(kolkata, 1700000000), 1699997400);
+        assert_eq!(BucketUnit::FiveMinutes.bucket_start(kolkata, 1700000000), 1699997400 + 40 * 60);
+        assert_eq!(BucketUnit::Hour.bucket_start(Tz::UTC, 1700000000), 1699999200);
+    }
+
+    #[test]
+    fn bucket_helpers_saturate_at_the_end_of_time() {
+        assert_eq!(BucketUnit::Hour.next_bucket_start(Tz::UTC, i64::MAX - 10), i64::MAX);
+        assert_eq!(BucketUnit::FiveMinutes.bucket_start(Tz::UTC, i64::MIN), i64::MIN);
+        assert_eq!(BucketUnit::Month.next_bucket_start(NEW_YORK, i64::MAX), i64::MAX);
+        assert_eq!(BucketUnit::Week.bucket_start(NEW_YORK, i64::MIN), i64::MIN);
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use axum::{extract::rejection::QueryRejection, http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::db::store::StoreError;
+
+/// One rejected query parameter
+#[derive(Debug, Clone, Serialize)]
+pub struct ParameterError {
+    pub parameter: String,
+    pub message: String,
+}
+
+/// Error returned by the API handlers.
+/// Serialized as `{"error": {"code", "message", "details"}}` where `code` is stable for clients to match on.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 400: one or more query parameters are malformed or out of range
+    InvalidParameter(Vec<ParameterError>),
+    /// 503: the database can't be reached right now
+    DatabaseUnavailable(String),
+    /// 500: anything else
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn invalid_parameter(parameter: &str, message: impl Into<String>) -> Self {
+        ApiError::InvalidParameter(vec![ParameterError {
+            parameter: parameter.to_string(),
+            message: message.into(),
+        }])
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidParameter(_) => StatusCode::BAD_REQUEST,
+            ApiError::DatabaseUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidParameter(_) => "INVALID_PARAMETER",
+            ApiError::DatabaseUnavailable(_) => "DATABASE_UNAVAILABLE",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidParameter(errors) => {
+                let messages: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.parameter, error.message))
+                    .collect();
+                write!(f, "invalid query parameters: {}", messages.join("; "))
+            }
+            ApiError::DatabaseUnavailable(_) => write!(f, "the database is temporarily unavailable"),
+            ApiError::Internal(_) => write!(f, "internal server error"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // ✅ Internal details are logged, never sent to clients
+        let details = match &self {
+            ApiError::InvalidParameter(errors) => json!(errors),
+            ApiError::DatabaseUnavailable(e) | ApiError::Internal(e) => {
+                println!("❌ {} ({}): {}", self.code(), self.status(), e);
+                json!([])
+            }
+        };
+
+        let body = json!({
+            "error": {
+                "code": self.code(),
+                "message": self.to_string(),
+                "details": details,
+            }
+        });
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(error: StoreError) -> Self {
+        match error {
+            StoreError::Unavailable(e) => ApiError::DatabaseUnavailable(e),
+            StoreError::Other(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::invalid_parameter("query", rejection.body_text())
+    }
+}
@@ -1,14 +1,25 @@
 use crate::db::mongo::connect_to_mongo;
+use crate::db::store::HistoryStore;
+use crate::db::mongo_store::MongoHistoryStore;
+use crate::db::memory_store::MemoryHistoryStore;
 use crate::config::settings::Settings;
-use tokio::time::{interval, Duration};
+use tokio::time::{interval, interval_at, Duration, Instant};
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use crate::api::create_api_router; // Import API Router
+use crate::api::{create_api_router, AppState}; // Import API Router
 use crate::utils::midgard_fetch::fetch_and_store_data; // Import the function to fetch and store data
-use tracing_subscriber;
+use crate::utils::midgard_client::client_from_settings;
+use crate::utils::midgard_fetch::fetch_pool_list;
+use crate::utils::backfill::{run_backfill, BackfillJob};
+use crate::utils::integrity::check_integrity;
+use crate::utils::legacy::migrate_legacy;
+use crate::cli::{BackfillArgs, Command, ServeArgs, USAGE};
+use crate::db::models::HistoryKind;
 use tracing:: Level;
 
+mod cli;
 mod config;
+mod error;
 mod db;
 mod utils;
 mod api;
@@ -20,32 +31,129 @@ async fn main() {
         .with_max_level(Level::INFO)//you’ll only write down important updates—like "Server is running" or "Connected to the database"
         .init();
 
-    // Load settings
-    let settings = Settings::new();
-    let db_client = connect_to_mongo(&settings.mongo_uri).await;
-    let db = Arc::new(db_client.database("midgard-vault"));
-
-    // Test Connection: Fetch Database Names
-    match db_client.list_database_names(None, None).await {
-        Ok(databases) => println!("✅ MongoDB Connected! Databases: {:?}", databases),
-        Err(e) => println!("❌ MongoDB Connection Failed: {:?}", e),
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match Command::parse(&args) {
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("❌ {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
     };
 
-    // ✅ Create API Router
-    let app = create_api_router(Arc::clone(&db));
+    // Load settings
+    let settings = Arc::new(Settings::new());
+    match settings.store_backend.as_str() {
+        "memory" => {
+            println!("⚠️ Using the in-memory store: data is lost on restart");
+            run(Arc::new(MemoryHistoryStore::new()), settings, command).await;
+        }
+        "mongo" => {
+            let db_client = connect_to_mongo(&settings.mongo_uri).await;
+            let db = Arc::new(db_client.database("midgard-vault"));
 
-    // Start the scheduled job to fetch data every hour
-    tokio::spawn({
-        let db = Arc::clone(&db);
-        async move {
-            let mut interval = interval(Duration::from_secs(3600)); // Set the interval to 1 hour
-            loop {
-                interval.tick().await;
-                println!("🔄 Fetching fresh data...");
-                fetch_and_store_data(Arc::clone(&db)).await;
+            // Test Connection: Fetch Database Names
+            match db_client.list_database_names(None, None).await {
+                Ok(databases) => println!("✅ MongoDB Connected! Databases: {:?}", databases),
+                Err(e) => println!("❌ MongoDB Connection Failed: {:?}", e),
+            };
+
+            run(Arc::new(MongoHistoryStore::new(db)), settings, command).await;
+        }
+        other => panic!("STORE_BACKEND must be `mongo` or `memory`, got `{}`", other),
+    }
+}
+
+async fn run<S: HistoryStore>(store: Arc<S>, settings: Arc<Settings>, command: Command) {
+    // Unique (pool, startTime) indexes keep ingestion idempotent; read-only replicas leave them to the writer
+    if command.writes() {
+        if let Err(e) = store.migrate().await {
+            println!("❌ Failed to create interval indexes: {}", e);
+            if let Command::Migrate = command {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match command {
+        Command::Serve(args) => serve(store, settings, args).await,
+        Command::Ingest { once: true } => {
+            println!("🔄 Fetching fresh data...");
+            let midgard = client_from_settings(&settings);
+            if !fetch_and_store_data(store.as_ref(), midgard.as_ref(), &settings).await {
+                println!("❌ Ingestion stopped early for some collections; see `ingestion_state`");
+                std::process::exit(1);
+            }
+        }
+        Command::Ingest { once: false } => run_scheduler(store, settings).await,
+        Command::Backfill(args) => {
+            if !backfill(store.as_ref(), &settings, &args).await {
+                std::process::exit(1);
+            }
+        }
+        Command::Verify { repair } => {
+            let midgard = repair.then(|| client_from_settings(&settings));
+            let reports = check_integrity(store.as_ref(), midgard.as_deref()).await;
+            let issues: usize = reports.iter().map(|report| report.issues.len()).sum();
+            if issues > 0 {
+                println!("🚨 Found {} integrity issues in {} collections", issues, reports.iter().filter(|report| !report.issues.is_empty()).count());
+                std::process::exit(1);
+            }
+            println!("✅ No gaps or overlaps in {} collections", reports.len());
+        }
+        Command::Migrate => {
+            if !migrate_legacy(store.as_ref()).await {
+                std::process::exit(1);
             }
+            println!("✅ Store is migrated");
         }
-    });
+        Command::Help => println!("{}", USAGE),
+    }
+}
+
+/// Loads `args`' range for every requested type (and pool); `false` if any of them stopped early
+async fn backfill(store: &dyn HistoryStore, settings: &Settings, args: &BackfillArgs) -> bool {
+    let midgard = client_from_settings(settings);
+    let mut complete = true;
+
+    for &kind in &args.kinds {
+        let pools = match kind {
+            HistoryKind::Depth if !args.pools.is_empty() => args.pools.iter().cloned().map(Some).collect(),
+            HistoryKind::Depth if !settings.midgard_pools.is_empty() => settings.midgard_pools.iter().cloned().map(Some).collect(),
+            HistoryKind::Depth => fetch_pool_list(midgard.as_ref()).await.into_iter().map(Some).collect(),
+            _ => vec![None],
+        };
+        for pool in pools {
+            let job = BackfillJob { kind, pool, from: args.from, to: args.to, concurrency: args.concurrency, restart: args.restart };
+            let outcome = run_backfill(store, midgard.as_ref(), &job).await;
+            match outcome.error {
+                Some(e) => {
+                    complete = false;
+                    println!("❌ Backfill `{}` stopped early: {}", job.state_key(), e);
+                    println!("🔄 Rerun the same command to resume where it stopped");
+                }
+                None => println!("✅ Backfill `{}` is complete", job.state_key()),
+            }
+        }
+    }
+    complete
+}
+
+/// Starts the API server on top of `store`, with the scheduled jobs unless `args` turns them off
+async fn serve<S: HistoryStore>(store: Arc<S>, settings: Arc<Settings>, args: ServeArgs) {
+    // ✅ Create API Router
+    let app = create_api_router(AppState { store: Arc::clone(&store), max_limit: settings.api_max_limit });
+
+    if args.read_only {
+        println!("⚠️ Read-only: serving stored data without ingestion");
+    } else if args.no_scheduler {
+        println!("⚠️ Scheduler disabled: serving stored data without ingestion");
+    } else {
+        tokio::spawn(run_scheduler(Arc::clone(&store), Arc::clone(&settings)));
+    }
 
     // ✅ Start Server
     let addr = format!("0.0.0.0:{}", settings.port);
@@ -53,3 +161,29 @@ async fn main() {
     println!("🚀 Server running on http://localhost:{}", settings.port);
     axum::serve(listener, app).await.unwrap();
 }
+
+/// Fetches new data every hour and checks stored history for gaps and overlaps; never returns
+async fn run_scheduler<S: HistoryStore>(store: Arc<S>, settings: Arc<Settings>) {
+    // Integrity checks start one period after startup
+    if settings.integrity_check_interval_secs > 0 {
+        let store = Arc::clone(&store);
+        let midgard = settings.integrity_auto_repair.then(|| client_from_settings(&settings));
+        let period = Duration::from_secs(settings.integrity_check_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = interval_at(Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                println!("🔄 Checking stored history for gaps...");
+                check_integrity(store.as_ref(), midgard.as_deref()).await;
+            }
+        });
+    }
+
+    let midgard = client_from_settings(&settings);
+    let mut interval = interval(Duration::from_secs(3600)); // Set the interval to 1 hour
+    loop {
+        interval.tick().await;
+        println!("🔄 Fetching fresh data...");
+        fetch_and_store_data(store.as_ref(), midgard.as_ref(), &settings).await;
+    }
+}
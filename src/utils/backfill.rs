@@ -0,0 +1,196 @@
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+
+use crate::db::models::{HistoryKind, IngestionState};
+use crate::db::store::HistoryStore;
+use crate::utils::midgard_client::{HistoryRequest, MidgardClient};
+use crate::utils::midgard_fetch::{fetch_page, FetchOutcome};
+
+/// Hours fetched per Midgard request, the most Midgard returns at once
+const CHUNK_HOURS: i64 = 400;
+
+/// One history type (and pool) to load for a fixed time range
+#[derive(Debug, Clone)]
+pub struct BackfillJob {
+    pub kind: HistoryKind,
+    pub pool: Option<String>,
+    pub from: i64,
+    /// `None` resumes the end of an unfinished earlier run, or loads up to now
+    pub to: Option<i64>,
+    /// Midgard requests in flight at once
+    pub concurrency: usize,
+    /// Ignore the progress saved by an earlier, interrupted run
+    pub restart: bool,
+}
+
+impl BackfillJob {
+    /// `ingestion_state` entry recording how far a range starting at `from` got, so a rerun resumes
+    /// there whatever `to` it resolves. Separate from the hourly job's watermark, which a backfill never moves.
+    pub fn state_key(&self) -> String {
+        format!("backfill:{}:{}", IngestionState::key(self.kind.collection(), self.pool.as_deref()), self.from)
+    }
+}
+
+/// `from..to` split into Midgard-sized `(start, end)` windows, starting on a full hour
+pub fn windows(from: i64, to: i64) -> Vec<(i64, i64)> {
+    let mut windows = vec![];
+    let mut start = from - from.rem_euclid(3600);
+    while start < to {
+        let end = (start + CHUNK_HOURS * 3600).min(to);
+        windows.push((start, end));
+        start = end;
+    }
+    windows
+}
+
+/// Fetches and upserts the intervals starting in `start..end` (at most one Midgard page); returns how many were stored
+pub async fn load_window(
+    store: &dyn HistoryStore,
+    client: &dyn MidgardClient,
+    kind: HistoryKind,
+    pool: Option<&str>,
+    start: i64,
+    end: i64,
+) -> Result<usize, String> {
+    let intervals = fetch_page(client, kind, pool, HistoryRequest::hourly_from(start, CHUNK_HOURS as u32)).await?;
+    // Midgard pages can run past the window; the next window (or nobody) owns those hours
+    let intervals: Vec<_> = intervals
+        .into_iter()
+        .filter(|interval| interval.get_i64("startTime").is_ok_and(|start_time| start_time >= start && start_time < end))
+        .collect();
+    store
+        .insert_intervals(kind, pool, intervals)
+        .await
+        .map_err(|e| format!("Failed to store intervals for {}: {}", kind.endpoint(pool), e))
+}
+
+/// Fetches and upserts every interval of `job`'s range. Intervals are keyed by `(pool, startTime)`,
+/// so loading a range that is already (partly) stored overwrites it instead of duplicating it.
+/// Windows are fetched `concurrency` at a time but committed in order: the saved progress only
+/// covers the unbroken prefix of stored windows, and the run stops at the first failure.
+pub async fn run_backfill(store: &dyn HistoryStore, client: &dyn MidgardClient, job: &BackfillJob) -> FetchOutcome {
+    let key = job.state_key();
+    let previous = match store.ingestion_state(&key).await {
+        Ok(previous) => previous.filter(|_| !job.restart),
+        Err(e) => {
+            return FetchOutcome { last_end_time: None, error: Some(format!("Failed to read backfill state `{}`: {}", key, e)) };
+        }
+    };
+    let resume_from = previous.as_ref().and_then(|state| state.last_end_time).unwrap_or(job.from).max(job.from);
+    // An unfinished run keeps the end it was started with, unless `to` overrides it
+    let unfinished = previous.as_ref().and_then(|state| state.target_end_time).filter(|target| resume_from < *target);
+    let to = job.to.or(unfinished).unwrap_or_else(|| Utc::now().timestamp());
+    if job.from >= to {
+        return FetchOutcome { last_end_time: None, error: Some(format!("--from ({}) must be before --to ({})", job.from, to)) };
+    }
+    if resume_from >= to {
+        println!("✅ Backfill `{}` already completed up to {}; pass --restart to load it again", key, to);
+        return FetchOutcome { last_end_time: Some(to), error: None };
+    }
+    if resume_from > job.from {
+        println!("🔄 Resuming backfill `{}` from {} to {}", key, resume_from, to);
+    }
+
+    let mut state = previous.unwrap_or_else(|| IngestionState {
+        id: key.clone(),
+        collection: job.kind.collection().to_string(),
+        pool: job.pool.clone(),
+        last_end_time: None,
+        last_attempt: None,
+        last_success: None,
+        last_error: None,
+        target_end_time: None,
+    });
+    state.last_attempt = Some(Utc::now().timestamp());
+    state.target_end_time = Some(to);
+
+    let windows = windows(resume_from, to);
+    let total = windows.len();
+    let pool = job.pool.as_deref();
+    let mut pages = stream::iter(windows)
+        .map(|(start, end)| async move {
+            load_window(store, client, job.kind, pool, start, end).await.map(|count| (start, end, count))
+        })
+        .buffered(job.concurrency.max(1));
+
+    let mut outcome = FetchOutcome::default();
+    let mut done = 0;
+    while let Some(page) = pages.next().await {
+        match page {
+            Ok((start, end, count)) => {
+                done += 1;
+                println!("✅ Backfill `{}`: {}/{} windows, {}..{} stored {} intervals", key, done, total, start, end, count);
+                outcome.last_end_time = Some(end);
+                state.last_end_time = Some(end);
+            }
+            Err(e) => {
+                outcome.error = Some(e);
+                break;
+            }
+        }
+        if let Err(e) = store.save_ingestion_state(&state).await {
+            println!("❌ Failed to save backfill state `{}`: {}", key, e);
+        }
+    }
+
+    state.last_error = outcome.error.clone();
+    if outcome.error.is_none() {
+        state.last_success = state.last_attempt;
+    }
+    if let Err(e) = store.save_ingestion_state(&state).await {
+        println!("❌ Failed to save backfill state `{}`: {}", key, e);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_store::MemoryHistoryStore;
+    use crate::utils::test_support::FixtureDir;
+
+    const START: i64 = 1_700_000_000 - 1_700_000_000 % 3600;
+
+    fn job(to: i64) -> BackfillJob {
+        BackfillJob { kind: HistoryKind::Swaps, pool: None, from: START, to: Some(to), concurrency: 2, restart: false }
+    }
+
+    #[test]
+    fn windows_start_on_the_hour_and_hold_at_most_a_page() {
+        assert_eq!(windows(START + 600, START + 7200), vec![(START, START + 7200)]);
+        let windows = windows(START, START + 1000 * 3600);
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[1], (START + CHUNK_HOURS * 3600, START + 2 * CHUNK_HOURS * 3600));
+        assert_eq!(windows[2].1, START + 1000 * 3600);
+    }
+
+    #[tokio::test]
+    async fn a_longer_rerun_resumes_after_the_stored_range() {
+        let fixtures = FixtureDir::new();
+        fixtures.record_swaps(&[(START, "1"), (START + 3600, "2"), (START + 7200, "3")]);
+        let client = fixtures.client();
+        let store = MemoryHistoryStore::new();
+
+        let outcome = run_backfill(&store, &client, &job(START + 7200)).await;
+        assert_eq!((outcome.last_end_time, outcome.error), (Some(START + 7200), None));
+        assert_eq!(store.interval_spans(HistoryKind::Swaps, None).await.unwrap().len(), 2);
+
+        // Same start, so the same progress: only the third hour is fetched
+        let state = store.ingestion_state(&job(START + 10_800).state_key()).await.unwrap().unwrap();
+        assert_eq!((state.last_end_time, state.target_end_time), (Some(START + 7200), Some(START + 7200)));
+        let outcome = run_backfill(&store, &client, &job(START + 10_800)).await;
+        assert_eq!((outcome.last_end_time, outcome.error), (Some(START + 10_800), None));
+        assert_eq!(store.interval_spans(HistoryKind::Swaps, None).await.unwrap().len(), 3);
+
+        // Nothing left to load
+        let outcome = run_backfill(&store, &client, &job(START + 10_800)).await;
+        assert_eq!(outcome.last_end_time, Some(START + 10_800));
+
+        // Without `to`, an interrupted run finishes the range it was started with
+        let mut state = store.ingestion_state(&job(START).state_key()).await.unwrap().unwrap();
+        state.last_end_time = Some(START + 3600);
+        store.save_ingestion_state(&state).await.unwrap();
+        let outcome = run_backfill(&store, &client, &BackfillJob { to: None, ..job(START) }).await;
+        assert_eq!((outcome.last_end_time, outcome.error), (Some(START + 10_800), None));
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::Utc;
+
+use crate::db::models::{HistoryKind, IngestionState, IntegrityIssue, IntegrityReport, IssueKind};
+use crate::db::store::{HistoryStore, StoreError};
+use crate::utils::backfill::{load_window, windows};
+use crate::utils::midgard_client::MidgardClient;
+
+/// Compares every pair of consecutive intervals (`spans` ordered by `startTime`).
+/// Hourly intervals should tile time exactly: each one starts where the previous one ended.
+pub fn find_issues(spans: &[(i64, i64)]) -> Vec<IntegrityIssue> {
+    let issue = |kind, start_time, end_time| IntegrityIssue { kind, start_time, end_time, repair: None };
+    let mut issues = vec![];
+
+    for pair in spans.windows(2) {
+        let ((previous_start, previous_end), (start, end)) = (pair[0], pair[1]);
+        if start == previous_start {
+            issues.push(issue(IssueKind::Duplicate, start, end.max(previous_end)));
+        } else if start < previous_end {
+            issues.push(issue(IssueKind::Overlap, start, previous_end));
+        } else if start > previous_end {
+            // Whole hours missing, or only a sliver between misaligned intervals
+            let next_hour = previous_end + (3600 - previous_end.rem_euclid(3600)) % 3600;
+            let kind = if next_hour + 3600 <= start { IssueKind::Missing } else { IssueKind::Discontinuity };
+            issues.push(issue(kind, previous_end, start));
+        }
+    }
+    issues
+}
+
+/// Scans one collection (and pool) and records the result in `integrity_reports`.
+/// With `client`, gaps are refetched from Midgard; overlaps and duplicates are only reported,
+/// since refetching can't remove intervals that shouldn't be there.
+pub async fn check_collection(
+    store: &dyn HistoryStore,
+    client: Option<&dyn MidgardClient>,
+    kind: HistoryKind,
+    pool: Option<&str>,
+) -> Result<IntegrityReport, StoreError> {
+    let key = IngestionState::key(kind.collection(), pool);
+    let spans = store.interval_spans(kind, pool).await?;
+    let mut issues = find_issues(&spans);
+
+    // ✅ Only the issues the previous check didn't already report are news
+    let previous = store.integrity_report(&key).await?;
+    let known = |issue: &IntegrityIssue| {
+        previous.as_ref().is_some_and(|report| {
+            report.issues.iter().any(|old| old.kind == issue.kind && old.start_time == issue.start_time && old.end_time == issue.end_time)
+        })
+    };
+    let new_issues = issues.iter().filter(|issue| !known(issue)).count();
+
+    if issues.is_empty() {
+        println!("✅ `{}`: {} intervals, no gaps or overlaps", key, spans.len());
+    } else {
+        println!("🚨 `{}`: {} integrity issues ({} new) in {} intervals", key, issues.len(), new_issues, spans.len());
+        for issue in issues.iter().take(10) {
+            println!("   {:?} {}..{}", issue.kind, issue.start_time, issue.end_time);
+        }
+    }
+
+    if let Some(client) = client {
+        for issue in issues.iter_mut().filter(|issue| matches!(issue.kind, IssueKind::Missing | IssueKind::Discontinuity)) {
+            let mut stored = 0;
+            let mut error = None;
+            for (start, end) in windows(issue.start_time, issue.end_time) {
+                match load_window(store, client, kind, pool, start, end).await {
+                    Ok(count) => stored += count,
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+            issue.repair = Some(match error {
+                None => {
+                    println!("🔄 `{}`: refetched {}..{}, stored {} intervals", key, issue.start_time, issue.end_time, stored);
+                    format!("refetched {} intervals", stored)
+                }
+                Some(e) => {
+                    println!("❌ `{}`: refetching {}..{} failed: {}", key, issue.start_time, issue.end_time, e);
+                    format!("failed: {}", e)
+                }
+            });
+        }
+    }
+
+    let report = IntegrityReport {
+        id: key,
+        collection: kind.collection().to_string(),
+        pool: pool.map(str::to_string),
+        checked_at: Utc::now().timestamp(),
+        intervals: spans.len() as u64,
+        first_start_time: spans.first().map(|span| span.0),
+        last_end_time: spans.last().map(|span| span.1),
+        issues,
+    };
+    store.save_integrity_report(&report).await?;
+    Ok(report)
+}
+
+/// Checks every history collection and every stored pool; returns the reports of the ones that could be read
+pub async fn check_integrity(store: &dyn HistoryStore, client: Option<&dyn MidgardClient>) -> Vec<IntegrityReport> {
+    let mut reports = vec![];
+    for kind in HistoryKind::ALL {
+        let pools = match store.stored_pools(kind).await {
+            Ok(pools) => pools,
+            Err(e) => {
+                println!("❌ Failed to list stored pools of `{}`: {}", kind.collection(), e);
+                continue;
+            }
+        };
+        for pool in pools {
+            match check_collection(store, client, kind, pool.as_deref()).await {
+                Ok(report) => reports.push(report),
+                Err(e) => println!("❌ Integrity check of `{}` failed: {}", IngestionState::key(kind.collection(), pool.as_deref()), e),
+            }
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+
+    fn kinds_and_spans(spans: &[(i64, i64)]) -> Vec<(IssueKind, i64, i64)> {
+        find_issues(spans).into_iter().map(|issue| (issue.kind, issue.start_time, issue.end_time)).collect()
+    }
+
+    #[test]
+    fn contiguous_hours_have_no_issues() {
+        let spans: Vec<(i64, i64)> = (0..5).map(|hour| (hour * HOUR, (hour + 1) * HOUR)).collect();
+        assert!(find_issues(&spans).is_empty());
+        assert!(find_issues(&[]).is_empty());
+        assert!(find_issues(&[(0, HOUR)]).is_empty());
+    }
+
+    #[test]
+    fn classifies_gaps_duplicates_and_overlaps() {
+        let spans = [
+            (0, HOUR),
+            // Two whole hours missing
+            (3 * HOUR, 4 * HOUR),
+            // Stored twice
+            (3 * HOUR, 4 * HOUR),
+            // Starts half an hour before the previous one ended
+            (4 * HOUR - 1800, 5 * HOUR),
+            // Less than an hour missing
+            (5 * HOUR + 600, 6 * HOUR),
+        ];
+        assert_eq!(
+            kinds_and_spans(&spans),
+            vec![
+                (IssueKind::Missing, HOUR, 3 * HOUR),
+                (IssueKind::Duplicate, 3 * HOUR, 4 * HOUR),
+                (IssueKind::Overlap, 4 * HOUR - 1800, 4 * HOUR),
+                (IssueKind::Discontinuity, 5 * HOUR, 5 * HOUR + 600),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_gap_is_missing_only_when_a_whole_hour_fits() {
+        // From a misaligned end, the next whole hour doesn't fit before the next start
+        assert_eq!(kinds_and_spans(&[(0, HOUR + 600), (2 * HOUR + 300, 3 * HOUR)]), vec![(IssueKind::Discontinuity, HOUR + 600, 2 * HOUR + 300)]);
+        assert_eq!(kinds_and_spans(&[(0, HOUR + 600), (3 * HOUR, 4 * HOUR)]), vec![(IssueKind::Missing, HOUR + 600, 3 * HOUR)]);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use mongodb::bson::{self, Document};
+
+use crate::db::models::{
+    DepthHistoryDocument, EarningsHistoryDocument, HistoryDocument, HistoryKind, IngestionState, RunePoolHistoryDocument,
+    SwapsHistoryDocument, DEFAULT_POOL,
+};
+use crate::db::store::{HistoryStore, StoreError};
+use crate::utils::midgard_fetch::to_stored_document;
+
+/// Pool a legacy page belongs to. Depth pages stored before multi-pool ingestion have no `pool` and are all BTC.BTC.
+pub fn legacy_pool(kind: HistoryKind, page: &Document) -> Option<String> {
+    match kind {
+        HistoryKind::Depth => Some(page.get_str("pool").unwrap_or(DEFAULT_POOL).to_string()),
+        _ => None,
+    }
+}
+
+/// The intervals of one legacy page, re-encoded like freshly fetched ones (amounts as `Decimal128`)
+pub fn legacy_intervals(kind: HistoryKind, page: &Document) -> Result<Vec<Document>, String> {
+    match kind {
+        HistoryKind::Depth => decode_intervals::<DepthHistoryDocument>(page),
+        HistoryKind::Earnings => decode_intervals::<EarningsHistoryDocument>(page),
+        HistoryKind::Swaps => decode_intervals::<SwapsHistoryDocument>(page),
+        HistoryKind::RunePool => decode_intervals::<RunePoolHistoryDocument>(page),
+    }
+}
+
+fn decode_intervals<T: HistoryDocument>(page: &Document) -> Result<Vec<Document>, String> {
+    let intervals = page.get_array("intervals").map_err(|e| e.to_string())?;
+    intervals
+        .iter()
+        .map(|interval| {
+            let interval: T::Interval = bson::from_bson(interval.clone()).map_err(|e| e.to_string())?;
+            to_stored_document(&interval)
+        })
+        .collect()
+}
+
+/// Copies one history type's legacy pages into its interval collection and carries the hourly
+/// job's watermark over, so ingestion resumes after the migrated data instead of refetching it.
+/// Intervals are upserted by `(pool, startTime)`, so running it again changes nothing.
+pub async fn migrate_legacy_kind(store: &dyn HistoryStore, kind: HistoryKind) -> Result<usize, StoreError> {
+    let pages = store.legacy_pages(kind).await?;
+    let mut migrated = 0;
+    // Latest migrated `endTime` per pool
+    let mut watermarks: BTreeMap<Option<String>, i64> = BTreeMap::new();
+
+    for page in &pages {
+        let pool = legacy_pool(kind, page);
+        let intervals = match legacy_intervals(kind, page) {
+            Ok(intervals) => intervals,
+            Err(e) => {
+                println!("⚠️ Skipping a `{}` page that doesn't decode: {}", kind.legacy_collection(), e);
+                continue;
+            }
+        };
+        if let Some(end_time) = intervals.iter().filter_map(|interval| interval.get_i64("endTime").ok()).max() {
+            let watermark = watermarks.entry(pool.clone()).or_insert(end_time);
+            *watermark = (*watermark).max(end_time);
+        }
+        migrated += store.insert_intervals(kind, pool.as_deref(), intervals).await?;
+    }
+
+    // Ingestion state written before the migration is keyed by the legacy collection
+    for pool in store.stored_pools(kind).await? {
+        let legacy_key = IngestionState::key(kind.legacy_collection(), pool.as_deref());
+        if let Some(end_time) = store.ingestion_state(&legacy_key).await?.and_then(|state| state.last_end_time) {
+            let watermark = watermarks.entry(pool).or_insert(end_time);
+            *watermark = (*watermark).max(end_time);
+        }
+    }
+
+    for (pool, end_time) in watermarks {
+        let key = IngestionState::key(kind.collection(), pool.as_deref());
+        let mut state = store.ingestion_state(&key).await?.unwrap_or_else(|| IngestionState {
+            id: key.clone(),
+            collection: kind.collection().to_string(),
+            pool: pool.clone(),
+            last_end_time: None,
+            last_attempt: None,
+            last_success: None,
+            last_error: None,
+            target_end_time: None,
+        });
+        // ✅ Only ever move the watermark forward
+        if state.last_end_time.is_some_and(|last_end_time| last_end_time >= end_time) {
+            continue;
+        }
+        state.last_end_time = Some(end_time);
+        store.save_ingestion_state(&state).await?;
+        println!("✅ `{}` resumes from the migrated watermark {}", key, end_time);
+    }
+    Ok(migrated)
+}
+
+/// Migrates every history type's legacy pages; `false` if any of them failed
+pub async fn migrate_legacy(store: &dyn HistoryStore) -> bool {
+    let mut complete = true;
+    for kind in HistoryKind::ALL {
+        match migrate_legacy_kind(store, kind).await {
+            Ok(0) => {}
+            Ok(count) => println!("✅ Migrated {} intervals from `{}` to `{}`", count, kind.legacy_collection(), kind.collection()),
+            Err(e) => {
+                complete = false;
+                println!("❌ Failed to migrate `{}`: {}", kind.legacy_collection(), e);
+            }
+        }
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Bson};
+
+    use super::*;
+    use crate::db::memory_store::MemoryHistoryStore;
+
+    /// A depth page as the first versions stored it: floats for amounts and no `pool`
+    fn legacy_depth_page() -> Document {
+        doc! {
+            "meta": { "startTime": 1_700_000_000_i64, "endTime": 1_700_003_600_i64 },
+            "intervals": [{
+                "assetDepth": 120346183421.0,
+                "assetPrice": 15816.3,
+                "assetPriceUSD": 61000.5,
+                "liquidityUnits": 985531284773150.0,
+                "membersCount": 1420,
+                "runeDepth": 1903445120930091.0,
+                "startTime": 1_700_000_000_i64,
+                "endTime": 1_700_003_600_i64,
+                "synthSupply": 0.0,
+                "synthUnits": 0.0,
+                "units": 985531284773150.0,
+                "luvi": 1.04,
+            }],
+        }
+    }
+
+    #[test]
+    fn legacy_depth_pages_become_btc_intervals_with_exact_amounts() {
+        let page = legacy_depth_page();
+        assert_eq!(legacy_pool(HistoryKind::Depth, &page).as_deref(), Some(DEFAULT_POOL));
+        assert_eq!(legacy_pool(HistoryKind::Swaps, &page), None);
+
+        let intervals = legacy_intervals(HistoryKind::Depth, &page).unwrap();
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].get_i64("startTime").unwrap(), 1_700_000_000);
+        assert!(matches!(intervals[0].get("runeDepth"), Some(Bson::Decimal128(d)) if d.to_string() == "1903445120930091"));
+    }
+
+    #[tokio::test]
+    async fn watermarks_written_under_legacy_keys_carry_over() {
+        let store = MemoryHistoryStore::default();
+        let interval = legacy_intervals(HistoryKind::Depth, &legacy_depth_page()).unwrap();
+        store.insert_intervals(HistoryKind::Depth, Some("BTC.BTC"), interval).await.unwrap();
+        let legacy_key = IngestionState::key(HistoryKind::Depth.legacy_collection(), Some("BTC.BTC"));
+        store
+            .save_ingestion_state(&IngestionState {
+                id: legacy_key,
+                collection: HistoryKind::Depth.legacy_collection().to_string(),
+                pool: Some("BTC.BTC".to_string()),
+                last_end_time: Some(1_700_007_200),
+                last_attempt: None,
+                last_success: None,
+                last_error: None,
+                target_end_time: None,
+            })
+            .await
+            .unwrap();
+
+        migrate_legacy_kind(&store, HistoryKind::Depth).await.unwrap();
+        let state = store.ingestion_state("depth_intervals:BTC.BTC").await.unwrap().unwrap();
+        assert_eq!(state.last_end_time, Some(1_700_007_200));
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::config::settings::Settings;
+use crate::db::models::{DepthHistoryDocument, EarningsHistoryDocument, RunePoolHistoryDocument, SwapsHistoryDocument};
+use crate::utils::midgard_fixture::FixtureMidgardClient;
+use crate::utils::midgard_http::{MidgardError, MidgardHttp};
+
+/// Query for a Midgard `/v2/history/*` endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRequest {
+    pub interval: String,
+    pub count: u32,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl HistoryRequest {
+    /// `count` hourly intervals starting at `from`, the page size the ingester uses
+    pub fn hourly_from(from: i64, count: u32) -> Self {
+        Self { interval: "hour".to_string(), count, from: Some(from), to: None }
+    }
+
+    pub fn query_string(&self) -> String {
+        let mut query = format!("interval={}&count={}", self.interval, self.count);
+        if let Some(from) = self.from {
+            query.push_str(&format!("&from={}", from));
+        }
+        if let Some(to) = self.to {
+            query.push_str(&format!("&to={}", to));
+        }
+        query
+    }
+}
+
+/// Typed access to the Midgard endpoints the ingester uses.
+/// `MidgardHttp` talks to real Midgard instances; `FixtureMidgardClient` replays recorded JSON.
+#[async_trait]
+pub trait MidgardClient: Send + Sync {
+    /// Assets of all pools known to Midgard (`/v2/pools`)
+    async fn pools(&self) -> Result<Vec<String>, MidgardError>;
+
+    async fn depth_history(&self, pool: &str, request: HistoryRequest) -> Result<DepthHistoryDocument, MidgardError>;
+
+    async fn earnings_history(&self, request: HistoryRequest) -> Result<EarningsHistoryDocument, MidgardError>;
+
+    async fn swaps_history(&self, request: HistoryRequest) -> Result<SwapsHistoryDocument, MidgardError>;
+
+    async fn runepool_history(&self, request: HistoryRequest) -> Result<RunePoolHistoryDocument, MidgardError>;
+}
+
+/// The client configured in `settings`: recorded fixtures when `MIDGARD_FIXTURES_DIR` is set, live Midgard otherwise
+pub fn client_from_settings(settings: &Settings) -> Arc<dyn MidgardClient> {
+    match &settings.midgard_fixtures_dir {
+        Some(dir) => {
+            println!("🧪 Replaying Midgard fixtures from {}", dir);
+            Arc::new(FixtureMidgardClient::new(dir))
+        }
+        None => Arc::new(MidgardHttp::new(settings)),
+    }
+}
+
+/// Deserializes a Midgard response into one of our typed documents
+pub fn decode<T: DeserializeOwned>(json: Value) -> Result<T, MidgardError> {
+    serde_json::from_value(json).map_err(|e| MidgardError::Decode(e.to_string()))
+}
+
+/// Pulls the `asset` of every pool out of a `/v2/pools` response
+pub fn pool_assets(json: Value) -> Result<Vec<String>, MidgardError> {
+    let pools: Vec<Value> = decode(json)?;
+    Ok(pools
+        .iter()
+        .filter_map(|pool| pool.get("asset")?.as_str().map(str::to_string))
+        .collect())
+}
+
+#[async_trait]
+impl MidgardClient for MidgardHttp {
+    async fn pools(&self) -> Result<Vec<String>, MidgardError> {
+        pool_assets(self.get_json("pools").await?)
+    }
+
+    async fn depth_history(&self, pool: &str, request: HistoryRequest) -> Result<DepthHistoryDocument, MidgardError> {
+        decode(self.get_json(&format!("history/depths/{}?{}", pool, request.query_string())).await?)
+    }
+
+    async fn earnings_history(&self, request: HistoryRequest) -> Result<EarningsHistoryDocument, MidgardError> {
+        decode(self.get_json(&format!("history/earnings?{}", request.query_string())).await?)
+    }
+
+    async fn swaps_history(&self, request: HistoryRequest) -> Result<SwapsHistoryDocument, MidgardError> {
+        decode(self.get_json(&format!("history/swaps?{}", request.query_string())).await?)
+    }
+
+    async fn runepool_history(&self, request: HistoryRequest) -> Result<RunePoolHistoryDocument, MidgardError> {
+        decode(self.get_json(&format!("history/runepool?{}", request.query_string())).await?)
+    }
+}
@@ -1,149 +1,324 @@
-use reqwest::Client;
-use mongodb::{bson::doc, Collection, Database};
+use mongodb::bson::{self, Document};
+use serde::Serialize;
 use chrono::Utc;
-use std::sync::Arc;
-use serde_json::Value;
+use std::future::Future;
 
 
-use crate::db::models::{DepthHistoryDocument, EarningsHistoryDocument, RunePoolHistoryDocument, SwapsHistoryDocument};
+use crate::config::settings::Settings;
+use crate::utils::midgard_client::{HistoryRequest, MidgardClient};
+use crate::utils::midgard_http::MidgardError;
+use crate::db::models::{HistoryDocument, HistoryKind, IngestionState, DEFAULT_POOL};
+use crate::db::store::HistoryStore;
 
-const MIDGARD_BASE_URL: &str = "https://midgard.ninerealms.com/v2/history";
+/// Result of one paginated fetch run for a single collection (and pool)
+#[derive(Debug, Default)]
+pub struct FetchOutcome {
+    /// `meta.endTime` of the last page that was stored successfully
+    pub last_end_time: Option<i64>,
+    /// Error that stopped the run early, if any
+    pub error: Option<String>,
+}
 
-/// Fetches and stores only new hourly data, avoiding duplicates
-pub async fn fetch_and_store_data(db: Arc<Database>) {
-    let client = Client::new();
+/// Fetches and stores only new hourly data, avoiding duplicates.
+/// Every history type (and every depth pool) resumes from its own watermark in `ingestion_state`.
+/// Returns `false` if any of them stopped early.
+pub async fn fetch_and_store_data(store: &dyn HistoryStore, client: &dyn MidgardClient, settings: &Settings) -> bool {
     let now = Utc::now().timestamp();
+    let mut complete = true;
+
+    // ✅ Depth history is per pool, so every pool has its own watermark
+    let pools = if settings.midgard_pools.is_empty() {
+        fetch_pool_list(client).await
+    } else {
+        settings.midgard_pools.clone()
+    };
+    for pool in &pools {
+        let start_time = get_resume_timestamp(store, HistoryKind::Depth, Some(pool), now).await;
+        let outcome = fetch_and_store_depth_history(client, store, pool, start_time, now).await;
+        complete &= outcome.error.is_none();
+        save_ingestion_state(store, HistoryKind::Depth, Some(pool), now, &outcome).await;
+    }
+
+    let start_time = get_resume_timestamp(store, HistoryKind::Earnings, None, now).await;
+    let outcome = fetch_and_store_earnings_history(client, store, start_time, now).await;
+    complete &= outcome.error.is_none();
+    save_ingestion_state(store, HistoryKind::Earnings, None, now, &outcome).await;
 
-    // ✅ Find the latest timestamp in MongoDB, fallback to 6 months ago if empty
-    // let last_stored_time = get_last_stored_timestamp(&db, "depth_history").await.unwrap_or(1739512851);
-    let last_stored_time = get_last_stored_timestamp(&db, "depth_history").await.unwrap_or(now - (6 * 30 * 24 * 3600));
+    let start_time = get_resume_timestamp(store, HistoryKind::Swaps, None, now).await;
+    let outcome = fetch_and_store_swaps_history(client, store, start_time, now).await;
+    complete &= outcome.error.is_none();
+    save_ingestion_state(store, HistoryKind::Swaps, None, now, &outcome).await;
 
-    println!("🔄 Fetching new data from: {}", last_stored_time);
+    let start_time = get_resume_timestamp(store, HistoryKind::RunePool, None, now).await;
+    let outcome = fetch_and_store_rune_pool_history(client, store, start_time, now).await;
+    complete &= outcome.error.is_none();
+    save_ingestion_state(store, HistoryKind::RunePool, None, now, &outcome).await;
 
-    // ✅ Fetch only missing data
-    fetch_and_store_depth_history(&client, &db, last_stored_time, now).await;
-    fetch_and_store_earnings_history(&client, &db, last_stored_time, now).await;
-    fetch_and_store_swaps_history(&client, &db, last_stored_time, now).await;
-    fetch_and_store_rune_pool_history(&client, &db, last_stored_time, now).await;
+    complete
 }
 
-/// Fetch the latest stored `endTime` from MongoDB to resume fetching efficiently
-pub async fn get_last_stored_timestamp(db: &Arc<Database>, collection_name: &str) -> Option<i64> {
-    let collection: Collection<mongodb::bson::Document> = db.collection(collection_name);
-
-    // ✅ Find the most recent document sorted by `meta.endTime` in descending order
-    let filter = None;
-    let sort = doc! { "meta.endTime": -1 };  // Sort in descending order (latest first)
-    let find_options = mongodb::options::FindOneOptions::builder().sort(sort).build();
-
-    match collection.find_one(filter, find_options).await {
-        Ok(Some(document)) => {
-            if let Some(end_time) = document.get("meta").and_then(|meta| {
-                meta.as_document()?.get("endTime")?.as_i64()
-            }) {
-                println!("✅ Last stored `meta.endTime` found: {}", end_time);
-                return Some(end_time);
-            } else {
-                println!("⚠️ `meta.endTime` not found in the latest document.");
-            }
+/// Where the next fetch for `kind` (and `pool`) should start:
+/// the stored watermark, else the latest stored interval, else 6 months ago
+pub async fn get_resume_timestamp(store: &dyn HistoryStore, kind: HistoryKind, pool: Option<&str>, now: i64) -> i64 {
+    let key = IngestionState::key(kind.collection(), pool);
+
+    match store.ingestion_state(&key).await {
+        Ok(Some(IngestionState { last_end_time: Some(end_time), .. })) => {
+            println!("✅ Resuming `{}` from watermark: {}", key, end_time);
+            return end_time;
+        }
+        Ok(_) => {}
+        Err(e) => println!("❌ Error reading ingestion state for `{}`: {}", key, e),
+    }
+
+    // ✅ No watermark yet: fall back to the data already stored
+    get_last_stored_timestamp(store, kind, pool)
+        .await
+        .unwrap_or(now - (6 * 30 * 24 * 3600))
+}
+
+/// Records the outcome of a fetch run in `ingestion_state`
+pub async fn save_ingestion_state(store: &dyn HistoryStore, kind: HistoryKind, pool: Option<&str>, attempted_at: i64, outcome: &FetchOutcome) {
+    let key = IngestionState::key(kind.collection(), pool);
+
+    let previous = match store.ingestion_state(&key).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            println!("❌ Failed to read ingestion state for `{}`: {}", key, e);
+            return;
         }
-        Ok(None) => println!("⚠️ No documents found in `{}`. Fetching from 6 months ago...", collection_name),
-        Err(e) => println!("❌ Error fetching `{}` latest timestamp: {:?}", collection_name, e),
+    };
+    let mut state = previous.unwrap_or_else(|| IngestionState {
+        id: key.clone(),
+        collection: kind.collection().to_string(),
+        pool: pool.map(str::to_string),
+        last_end_time: None,
+        last_attempt: None,
+        last_success: None,
+        last_error: None,
+        target_end_time: None,
+    });
+
+    state.last_attempt = Some(attempted_at);
+    state.last_error = outcome.error.clone();
+    // ✅ Only move the watermark forward when a page was actually stored
+    if let Some(end_time) = outcome.last_end_time {
+        state.last_end_time = Some(end_time);
+    }
+    if outcome.error.is_none() {
+        state.last_success = Some(attempted_at);
+    }
+
+    match store.save_ingestion_state(&state).await {
+        Ok(()) => match &outcome.error {
+            Some(e) => println!("⚠️ `{}` stopped early: {}", key, e),
+            None => println!("✅ `{}` is up to date", key),
+        },
+        Err(e) => println!("❌ Failed to save ingestion state for `{}`: {}", key, e),
+    }
+}
+
+/// Fetch the list of pools known to Midgard, falling back to BTC.BTC if the lookup fails
+pub async fn fetch_pool_list(client: &dyn MidgardClient) -> Vec<String> {
+    match client.pools().await {
+        Ok(assets) => {
+            println!("✅ Discovered {} pools from Midgard", assets.len());
+            assets
+        }
+        Err(e) => {
+            println!("❌ Failed to fetch pool list, falling back to {}: {}", DEFAULT_POOL, e);
+            vec![DEFAULT_POOL.to_string()]
+        }
+    }
+}
+
+/// Fetch the latest stored `endTime` to resume fetching efficiently
+pub async fn get_last_stored_timestamp(store: &dyn HistoryStore, kind: HistoryKind, pool: Option<&str>) -> Option<i64> {
+    match store.last_end_time(kind, pool).await {
+        Ok(Some(end_time)) => {
+            println!("✅ Last stored `endTime` found: {}", end_time);
+            return Some(end_time);
+        }
+        Ok(None) => println!("⚠️ No documents found in `{}`. Fetching from 6 months ago...", kind.collection()),
+        Err(e) => println!("❌ Error fetching `{}` latest timestamp: {}", kind.collection(), e),
     }
 
     None  // If no records exist, return None (fetch from 6 months ago)
 }
 
-/// Fetch and store depth history
-async fn fetch_and_store_depth_history(client: &Client, db: &Arc<Database>, start_time: i64, end_time: i64) {
-    let collection: Collection<DepthHistoryDocument> = db.collection("depth_history");
-    fetch_paginated_data(client, &collection, "depths/BTC.BTC", start_time, end_time).await;
+/// Fetch and store depth history for a single pool
+async fn fetch_and_store_depth_history(client: &dyn MidgardClient, store: &dyn HistoryStore, pool: &str, start_time: i64, end_time: i64) -> FetchOutcome {
+    fetch_paginated_data(store, HistoryKind::Depth, Some(pool), start_time, end_time, |request| client.depth_history(pool, request)).await
 }
 
 /// Fetch and store earnings history
-async fn fetch_and_store_earnings_history(client: &Client, db: &Arc<Database>, start_time: i64, end_time: i64) {
-    let collection: Collection<EarningsHistoryDocument> = db.collection("earnings_history");
-    fetch_paginated_data(client, &collection, "earnings", start_time, end_time).await;
+async fn fetch_and_store_earnings_history(client: &dyn MidgardClient, store: &dyn HistoryStore, start_time: i64, end_time: i64) -> FetchOutcome {
+    fetch_paginated_data(store, HistoryKind::Earnings, None, start_time, end_time, |request| client.earnings_history(request)).await
 }
 
 /// Fetch and store swaps history
-async fn fetch_and_store_swaps_history(client: &Client, db: &Arc<Database>, start_time: i64, end_time: i64) {
-    let collection: Collection<SwapsHistoryDocument> = db.collection("swaps_history");
-    fetch_paginated_data(client, &collection, "swaps", start_time, end_time).await;
+async fn fetch_and_store_swaps_history(client: &dyn MidgardClient, store: &dyn HistoryStore, start_time: i64, end_time: i64) -> FetchOutcome {
+    fetch_paginated_data(store, HistoryKind::Swaps, None, start_time, end_time, |request| client.swaps_history(request)).await
 }
 
 /// Fetch and store rune pool history
-async fn fetch_and_store_rune_pool_history(client: &Client, db: &Arc<Database>, start_time: i64, end_time: i64) {
-    let collection: Collection<RunePoolHistoryDocument> = db.collection("rune_pool_history");
-    fetch_paginated_data(client, &collection, "runepool", start_time, end_time).await;
+async fn fetch_and_store_rune_pool_history(client: &dyn MidgardClient, store: &dyn HistoryStore, start_time: i64, end_time: i64) -> FetchOutcome {
+    fetch_paginated_data(store, HistoryKind::RunePool, None, start_time, end_time, |request| client.runepool_history(request)).await
+}
+
+/// Fetches one page of `kind` (and `pool`) and encodes its intervals for storage
+pub async fn fetch_page(client: &dyn MidgardClient, kind: HistoryKind, pool: Option<&str>, request: HistoryRequest) -> Result<Vec<Document>, String> {
+    let endpoint = kind.endpoint(pool);
+    let intervals = match kind {
+        HistoryKind::Depth => encode_page(client.depth_history(pool.unwrap_or(DEFAULT_POOL), request).await),
+        HistoryKind::Earnings => encode_page(client.earnings_history(request).await),
+        HistoryKind::Swaps => encode_page(client.swaps_history(request).await),
+        HistoryKind::RunePool => encode_page(client.runepool_history(request).await),
+    };
+    intervals.map_err(|e| format!("Failed to fetch {}: {}", endpoint, e))
 }
 
-/// Fetch paginated data from Midgard and store it in MongoDB
-async fn fetch_paginated_data<T>(
-    client: &Client,
-    collection: &Collection<T>,
-    endpoint: &str,
+fn encode_page<T: HistoryDocument>(page: Result<T, MidgardError>) -> Result<Vec<Document>, String> {
+    page.map_err(|e| e.to_string())?.into_intervals().iter().map(to_stored_document).collect()
+}
+
+/// Encodes an interval for storage. The raw serializer isn't human-readable, so amounts become `Decimal128` instead of strings.
+pub(crate) fn to_stored_document<T: Serialize>(interval: &T) -> Result<Document, String> {
+    bson::to_raw_document_buf(interval)
+        .map_err(|e| e.to_string())?
+        .to_document()
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch paginated data from Midgard and upsert its intervals into the store.
+/// Intervals are keyed by `(pool, startTime)`, so re-fetching an overlapping window overwrites them.
+/// `fetch_page` performs the typed Midgard call for one page of up to 400 hourly intervals.
+/// Stops at the first error so the watermark never skips past data that wasn't stored.
+async fn fetch_paginated_data<T, F, Fut>(
+    store: &dyn HistoryStore,
+    kind: HistoryKind,
+    pool: Option<&str>,
     start_time: i64,
     end_time: i64,
-) where
-    T: serde::de::DeserializeOwned + serde::Serialize + std::fmt::Debug,
+    fetch_page: F,
+) -> FetchOutcome
+where
+    T: HistoryDocument,
+    F: Fn(HistoryRequest) -> Fut,
+    Fut: Future<Output = Result<T, MidgardError>>,
 {
+    let endpoint = kind.endpoint(pool);
+    let mut outcome = FetchOutcome::default();
     let mut current_time = start_time;
 
     while current_time < end_time {
-        let url = format!(
-            "{}/{endpoint}?interval=hour&count=400&from={current_time}",
-            MIDGARD_BASE_URL
-        );
-
-        println!("🔄 Fetching data from: {}", url);  // ✅ Debugging log
-
-        match client.get(&url).send().await {
-            Ok(response) => {
-                match response.text().await {
-                    Ok(body) => {
-                        // println!("🔍 Response from {}:\n {}", endpoint, body);
-                        match serde_json::from_str::<Value>(&body) {
-                            Ok(json) => {
-                                match serde_json::from_value::<T>(json.clone()) {
-                                    Ok(doc) => {
-                                        match collection.insert_one(&doc, None).await {
-                                            Ok(_) => println!("✅ Inserted document into {}", endpoint),
-                                            Err(e) => println!("❌ Failed to insert record into {}: {:?}", endpoint, e),
-                                        }
-                                    }
-                                    Err(e) => println!("❌ Failed to deserialize full response for {}: {:?}", endpoint, e),
-                                }
-
-                                if let Some(meta) = json.get("meta") {
-                                    if let Some(end_time_str) = meta.get("endTime").and_then(|v| v.as_str()) {
-                                        if let Ok(new_start_time) = end_time_str.parse::<i64>() {
-                                            if new_start_time > current_time {
-                                                current_time = new_start_time;
-                                            } else {
-                                                println!("🚨 Warning: Pagination stopped early for {}", endpoint);
-                                                break;
-                                            }
-                                        } else {
-                                            println!("🚨 Warning: Failed to parse `meta.endTime` as i64 for {}", endpoint);
-                                            break;
-                                        }
-                                    } else {
-                                        println!("🚨 Warning: No `meta.endTime` found for {}", endpoint);
-                                        break;
-                                    }
-                                }
-                            }
-                            Err(e) => println!("❌ Failed to parse JSON for {}: {:?}", endpoint, e),
-                        }
-                    }
-                    Err(e) => println!("❌ Failed to read response body from {}: {:?}", endpoint, e),
-                }
+        // ✅ Retries, backoff, failover and rate limiting happen inside the client
+        let page = match fetch_page(HistoryRequest::hourly_from(current_time, 400)).await {
+            Ok(page) => page,
+            Err(e) => {
+                outcome.error = Some(format!("Failed to fetch {}: {}", endpoint, e));
+                break;
+            }
+        };
+
+        let new_start_time = page.end_time();
+        let intervals: Vec<Document> = match page.into_intervals().iter().map(to_stored_document).collect::<Result<Vec<_>, _>>() {
+            Ok(intervals) => intervals,
+            Err(e) => {
+                outcome.error = Some(format!("Failed to encode intervals for {}: {}", endpoint, e));
+                break;
             }
+        };
+
+        let fetched = intervals.len();
+        match store.insert_intervals(kind, pool, intervals).await {
+            Ok(count) => println!("✅ Upserted {} intervals into {}", count, kind.collection()),
             Err(e) => {
-                println!("❌ Failed to fetch {}: {:?}", endpoint, e);
+                outcome.error = Some(format!("Failed to store intervals for {}: {}", endpoint, e));
                 break;
             }
         }
+        outcome.last_end_time = Some(new_start_time);
+
+        if new_start_time > current_time {
+            current_time = new_start_time;
+        } else if new_start_time == current_time && fetched == 0 {
+            // ✅ Caught up: Midgard has nothing after the watermark yet
+            break;
+        } else {
+            println!("🚨 Warning: Pagination stopped early for {} ({} -> {})", endpoint, current_time, new_start_time);
+            break;
+        }
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_store::MemoryHistoryStore;
+    use crate::utils::test_support::{settings, FixtureDir};
+
+    /// A full hour ten days ago, inside the six months a first run fetches
+    fn start() -> i64 {
+        let now = Utc::now().timestamp();
+        now - now.rem_euclid(3600) - 10 * 86_400
+    }
+
+    #[tokio::test]
+    async fn reruns_resume_from_the_watermark_without_duplicates() {
+        let start = start();
+        let fixtures = FixtureDir::new();
+        fixtures.record_swaps(&[(start, "1"), (start + 3600, "2")]);
+        let store = MemoryHistoryStore::new();
+
+        let outcome = fetch_and_store_swaps_history(&fixtures.client(), &store, start, Utc::now().timestamp()).await;
+        assert_eq!((outcome.last_end_time, outcome.error.as_deref()), (Some(start + 7200), None));
+        save_ingestion_state(&store, HistoryKind::Swaps, None, start, &outcome).await;
+        assert_eq!(get_resume_timestamp(&store, HistoryKind::Swaps, None, Utc::now().timestamp()).await, start + 7200);
+
+        // A new hour arrives; the second run only fetches from the watermark on
+        fixtures.record_swaps(&[(start, "1"), (start + 3600, "2"), (start + 7200, "3")]);
+        let now = Utc::now().timestamp();
+        let resume = get_resume_timestamp(&store, HistoryKind::Swaps, None, now).await;
+        let outcome = fetch_and_store_swaps_history(&fixtures.client(), &store, resume, now).await;
+        assert_eq!(outcome.last_end_time, Some(start + 10_800));
+        let spans = store.interval_spans(HistoryKind::Swaps, None).await.unwrap();
+        assert_eq!(spans, vec![(start, start + 3600), (start + 3600, start + 7200), (start + 7200, start + 10_800)]);
+
+        // Caught up: an empty page ends the run without an error and keeps the watermark
+        let outcome = fetch_and_store_swaps_history(&fixtures.client(), &store, start + 10_800, now).await;
+        assert_eq!((outcome.last_end_time, outcome.error), (Some(start + 10_800), None));
+    }
+
+    #[tokio::test]
+    async fn a_missing_pool_recording_is_recorded_without_moving_its_watermark() {
+        let start = start();
+        let fixtures = FixtureDir::new();
+        fixtures.record_depths("BTC.BTC", &[(start, "1"), (start + 3600, "2")]);
+        let store = MemoryHistoryStore::new();
+        let previous = IngestionState {
+            id: "depth_intervals:ETH.ETH".to_string(),
+            collection: HistoryKind::Depth.collection().to_string(),
+            pool: Some("ETH.ETH".to_string()),
+            last_end_time: Some(start),
+            last_attempt: None,
+            last_success: None,
+            last_error: None,
+            target_end_time: None,
+        };
+        store.save_ingestion_state(&previous).await.unwrap();
+
+        let complete = fetch_and_store_data(&store, &fixtures.client(), &settings(&["BTC.BTC", "ETH.ETH"])).await;
+        assert!(!complete);
+
+        let btc = store.ingestion_state("depth_intervals:BTC.BTC").await.unwrap().unwrap();
+        assert_eq!((btc.last_end_time, btc.last_error), (Some(start + 7200), None));
+        let eth = store.ingestion_state("depth_intervals:ETH.ETH").await.unwrap().unwrap();
+        assert_eq!(eth.last_end_time, Some(start));
+        assert!(eth.last_error.is_some_and(|e| e.contains("depths/ETH.ETH") && e.contains("no fixture")));
+        assert_eq!(eth.last_success, None);
+        assert!(store.interval_spans(HistoryKind::Depth, Some("ETH.ETH")).await.unwrap().is_empty());
     }
 }
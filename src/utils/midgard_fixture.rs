@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::db::models::{DepthHistoryDocument, EarningsHistoryDocument, RunePoolHistoryDocument, SwapsHistoryDocument};
+use crate::utils::midgard_client::{decode, pool_assets, HistoryRequest, MidgardClient};
+use crate::utils::midgard_http::MidgardError;
+
+/// `MidgardClient` that replays recorded Midgard responses from a directory instead of the network.
+///
+/// Expected files: `pools.json`, `depths.<POOL>.json` (e.g. `depths.BTC.BTC.json`),
+/// `earnings.json`, `swaps.json` and `runepool.json`, each holding a raw Midgard response.
+/// History requests are answered with the recorded intervals that fall inside
+/// `from`/`to`, limited to `count`, so paginated ingestion walks through a recording
+/// just like it walks through live data.
+#[derive(Debug, Clone)]
+pub struct FixtureMidgardClient {
+    dir: PathBuf,
+}
+
+impl FixtureMidgardClient {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+
+    async fn load(&self, name: &str) -> Result<Value, MidgardError> {
+        let path = self.dir.join(format!("{}.json", name));
+        let body = tokio::fs::read_to_string(&path).await.map_err(|e| MidgardError::Status {
+            status: StatusCode::NOT_FOUND,
+            body: format!("no fixture at {}: {}", path.display(), e),
+        })?;
+        serde_json::from_str(&body).map_err(|e| MidgardError::Decode(e.to_string()))
+    }
+
+    async fn history(&self, name: &str, request: &HistoryRequest) -> Result<Value, MidgardError> {
+        let recorded = self.load(name).await?;
+        Ok(slice_history(recorded, request))
+    }
+}
+
+/// Keeps the recorded intervals matching `request` and points `meta.startTime`/`meta.endTime` at them
+fn slice_history(mut recorded: Value, request: &HistoryRequest) -> Value {
+    let from = request.from.unwrap_or(i64::MIN);
+    let to = request.to.unwrap_or(i64::MAX);
+
+    let intervals: Vec<Value> = recorded
+        .get("intervals")
+        .and_then(Value::as_array)
+        .map(|intervals| {
+            intervals
+                .iter()
+                .filter(|interval| {
+                    let start_time = timestamp(interval, "startTime").unwrap_or(i64::MIN);
+                    start_time >= from && start_time < to
+                })
+                .take(request.count as usize)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Midgard reports the requested window even when it holds no data
+    let start_time = intervals.first().and_then(|i| timestamp(i, "startTime")).or(request.from);
+    let end_time = intervals.last().and_then(|i| timestamp(i, "endTime")).or(request.from);
+
+    if let Some(meta) = recorded.get_mut("meta").and_then(Value::as_object_mut) {
+        if let Some(start_time) = start_time {
+            meta.insert("startTime".to_string(), Value::String(start_time.to_string()));
+        }
+        if let Some(end_time) = end_time {
+            meta.insert("endTime".to_string(), Value::String(end_time.to_string()));
+        }
+    }
+    if let Some(object) = recorded.as_object_mut() {
+        object.insert("intervals".to_string(), Value::Array(intervals));
+    }
+    recorded
+}
+
+/// Midgard timestamps are strings, but accept numbers too
+fn timestamp(interval: &Value, field: &str) -> Option<i64> {
+    let value = interval.get(field)?;
+    value.as_i64().or_else(|| value.as_str()?.parse().ok())
+}
+
+#[async_trait]
+impl MidgardClient for FixtureMidgardClient {
+    async fn pools(&self) -> Result<Vec<String>, MidgardError> {
+        pool_assets(self.load("pools").await?)
+    }
+
+    async fn depth_history(&self, pool: &str, request: HistoryRequest) -> Result<DepthHistoryDocument, MidgardError> {
+        decode(self.history(&format!("depths.{}", pool), &request).await?)
+    }
+
+    async fn earnings_history(&self, request: HistoryRequest) -> Result<EarningsHistoryDocument, MidgardError> {
+        decode(self.history("earnings", &request).await?)
+    }
+
+    async fn swaps_history(&self, request: HistoryRequest) -> Result<SwapsHistoryDocument, MidgardError> {
+        decode(self.history("swaps", &request).await?)
+    }
+
+    async fn runepool_history(&self, request: HistoryRequest) -> Result<RunePoolHistoryDocument, MidgardError> {
+        decode(self.history("runepool", &request).await?)
+    }
+}
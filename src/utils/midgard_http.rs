@@ -0,0 +1,467 @@
+use std::fmt;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::config::settings::Settings;
+
+/// Error returned once a Midgard request has failed for good
+#[derive(Debug)]
+pub enum MidgardError {
+    /// Midgard answered with a non-success status
+    Status { status: StatusCode, body: String },
+    /// The request never got a response (connect error, timeout, ...)
+    Network(String),
+    /// The response body wasn't the JSON we expected
+    Decode(String),
+}
+
+impl fmt::Display for MidgardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidgardError::Status { status, body } => write!(f, "Midgard returned {}: {}", status, body),
+            MidgardError::Network(e) => write!(f, "request to Midgard failed: {}", e),
+            MidgardError::Decode(e) => write!(f, "invalid response from Midgard: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MidgardError {}
+
+/// Retry behaviour for Midgard requests
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter: a random delay between half and all of
+    /// `base * 2^attempt`, capped at `max_delay`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let capped = exponential.min(self.max_delay);
+        let half = capped / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// Spaces requests at least `1 / requests_per_second` apart
+#[derive(Debug)]
+struct RateLimiter {
+    spacing: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Fails for a rate that is not a positive number, or too small to space requests with
+    fn new(requests_per_second: f64) -> Result<Self, String> {
+        let spacing = Some(requests_per_second)
+            .filter(|rate| *rate > 0.0)
+            .and_then(|rate| Duration::try_from_secs_f64(1.0 / rate).ok())
+            .filter(|spacing| Instant::now().checked_add(*spacing).is_some())
+            .ok_or_else(|| format!("requests per second must be a positive number, got {}", requests_per_second))?;
+        Ok(Self { spacing, next_slot: Mutex::new(Instant::now()) })
+    }
+
+    async fn acquire(&self) {
+        let mut next_slot = self.next_slot.lock().await;
+        let now = Instant::now();
+        if *next_slot > now {
+            sleep_until(*next_slot).await;
+        }
+        *next_slot = (*next_slot).max(now) + self.spacing;
+    }
+}
+
+/// Health bookkeeping for one Midgard instance
+#[derive(Debug, Default)]
+struct EndpointHealth {
+    consecutive_failures: u32,
+    /// Skip this endpoint until then (set after errors or a stale health check)
+    unhealthy_until: Option<Instant>,
+    /// When `/v2/health` was last probed
+    checked_at: Option<Instant>,
+}
+
+/// One configured Midgard base URL
+#[derive(Debug)]
+struct Endpoint {
+    base_url: String,
+    health: StdMutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    fn is_usable(&self, now: Instant) -> bool {
+        let health = self.health.lock().unwrap();
+        health.unhealthy_until.is_none_or(|until| until <= now)
+    }
+
+    fn needs_health_check(&self, now: Instant, interval: Duration) -> bool {
+        let health = self.health.lock().unwrap();
+        health.checked_at.is_none_or(|checked_at| now.duration_since(checked_at) >= interval)
+    }
+
+    fn mark_healthy(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures = 0;
+        health.unhealthy_until = None;
+    }
+
+    fn mark_unhealthy(&self, cooldown: Duration, reason: &str) {
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        health.unhealthy_until = Some(Instant::now() + cooldown);
+        println!("🚨 Midgard {} marked unhealthy ({} failures in a row): {}", self.base_url, health.consecutive_failures, reason);
+    }
+}
+
+/// HTTP layer in front of Midgard: per-request timeouts, a requests-per-second
+/// limiter, retries with exponential backoff that honor `Retry-After`, and
+/// failover across the configured base URLs in priority order
+#[derive(Debug)]
+pub struct MidgardHttp {
+    client: Client,
+    endpoints: Vec<Endpoint>,
+    retry: RetryPolicy,
+    limiter: RateLimiter,
+    max_lag: Duration,
+    cooldown: Duration,
+}
+
+impl MidgardHttp {
+    pub fn new(settings: &Settings) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.midgard_timeout_secs))
+            .build()
+            .expect("Failed to build Midgard HTTP client");
+
+        let endpoints = settings
+            .midgard_base_urls
+            .iter()
+            .map(|url| Endpoint {
+                base_url: url.trim_end_matches('/').to_string(),
+                health: StdMutex::new(EndpointHealth::default()),
+            })
+            .collect();
+
+        Self {
+            client,
+            endpoints,
+            retry: RetryPolicy {
+                max_retries: settings.midgard_max_retries,
+                base_delay: Duration::from_millis(settings.midgard_backoff_base_ms),
+                max_delay: Duration::from_millis(settings.midgard_backoff_max_ms),
+            },
+            limiter: RateLimiter::new(settings.midgard_requests_per_second)
+                .unwrap_or_else(|e| panic!("MIDGARD_REQUESTS_PER_SECOND: {}", e)),
+            max_lag: Duration::from_secs(settings.midgard_max_lag_secs),
+            cooldown: Duration::from_secs(settings.midgard_unhealthy_cooldown_secs),
+        }
+    }
+
+    /// GETs `path` (relative to `/v2`, e.g. `history/swaps?...`) and parses the body as JSON
+    pub async fn get_json(&self, path: &str) -> Result<Value, MidgardError> {
+        let path = path.trim_start_matches('/');
+        let mut attempt = 0;
+        let mut previous: Option<usize> = None;
+
+        loop {
+            let index = self.pick_endpoint().await;
+            let endpoint = &self.endpoints[index];
+            let url = format!("{}/v2/{}", endpoint.base_url, path);
+
+            self.limiter.acquire().await;
+            println!("🔄 Fetching data from: {}", url);
+
+            let (error, retry_after) = match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => {
+                    endpoint.mark_healthy();
+                    return response.json::<Value>().await.map_err(|e| MidgardError::Decode(e.to_string()));
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry_after(response.headers());
+                    let body = response.text().await.unwrap_or_default();
+                    let error = MidgardError::Status { status, body };
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) => (MidgardError::Network(e.to_string()), None),
+            };
+
+            endpoint.mark_unhealthy(self.cooldown, &error.to_string());
+            if attempt >= self.retry.max_retries {
+                return Err(error);
+            }
+            attempt += 1;
+
+            // ✅ Fail over right away when another endpoint is available, back off otherwise
+            let next = self.pick_endpoint().await;
+            if next != index && previous != Some(next) {
+                println!("⚠️ {} — failing over to {} (retry {}/{})", error, self.endpoints[next].base_url, attempt, self.retry.max_retries);
+            } else {
+                // 🚨 Retrying before `Retry-After` runs out only earns another 429, so give up
+                // instead when Midgard asks for a longer wait than `max_delay`
+                if let Some(wait) = retry_after.filter(|wait| *wait > self.retry.max_delay) {
+                    println!("🚨 {} — Midgard asked to wait {:?}, longer than the {:?} limit", error, wait, self.retry.max_delay);
+                    return Err(error);
+                }
+                let delay = retry_after.unwrap_or_else(|| self.retry.backoff(attempt - 1));
+                println!("⚠️ {} — retry {}/{} in {:?}", error, attempt, self.retry.max_retries, delay);
+                sleep(delay).await;
+            }
+            previous = Some(index);
+        }
+    }
+
+    /// Highest-priority endpoint that is neither failing nor stale.
+    /// When every endpoint is unhealthy, the one that recovers soonest is used anyway.
+    async fn pick_endpoint(&self) -> usize {
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            if !endpoint.is_usable(Instant::now()) {
+                continue;
+            }
+            if endpoint.needs_health_check(Instant::now(), self.cooldown) {
+                self.check_health(endpoint).await;
+            }
+            if endpoint.is_usable(Instant::now()) {
+                return index;
+            }
+        }
+
+        self.endpoints
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, endpoint)| endpoint.health.lock().unwrap().unhealthy_until)
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+
+    /// Probes `/v2/health` and marks the endpoint unhealthy if it is down,
+    /// not in sync, or its last aggregated block is older than `max_lag`
+    async fn check_health(&self, endpoint: &Endpoint) {
+        endpoint.health.lock().unwrap().checked_at = Some(Instant::now());
+
+        let url = format!("{}/v2/health", endpoint.base_url);
+        self.limiter.acquire().await;
+        let health = match self.client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => response.json::<Value>().await.map_err(|e| e.to_string()),
+            Ok(response) => Err(format!("health check returned {}", response.status())),
+            Err(e) => Err(e.to_string()),
+        };
+
+        let problem = match health {
+            Ok(health) => stale_reason(&health, self.max_lag),
+            Err(e) => Some(e),
+        };
+        match problem {
+            Some(reason) => endpoint.mark_unhealthy(self.cooldown, &reason),
+            None => endpoint.mark_healthy(),
+        }
+    }
+}
+
+/// Why a `/v2/health` response indicates stale data, if it does
+fn stale_reason(health: &Value, max_lag: Duration) -> Option<String> {
+    if health.get("inSync").and_then(Value::as_bool) == Some(false) {
+        return Some("Midgard is not in sync".to_string());
+    }
+
+    let timestamp = health.get("lastAggregated")?.get("timestamp")?;
+    let timestamp = timestamp.as_i64().or_else(|| timestamp.as_str()?.parse().ok())?;
+    let lag = Utc::now().timestamp() - timestamp;
+    if lag > max_lag.as_secs() as i64 {
+        return Some(format!("last aggregated block is {}s old", lag));
+    }
+    None
+}
+
+/// Rate limiting and server-side errors are worth retrying; other client errors are not
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Parses `Retry-After` given either as seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use axum::{extract::State, http::StatusCode as HttpStatus, routing::get, Json, Router};
+    use serde_json::json;
+
+    use super::*;
+    use crate::utils::test_support;
+
+    /// A local stand-in for one Midgard instance; `/v2/history/swaps` answers with its `name`
+    /// while `up`, `503` otherwise, and `/v2/health` reports `inSync: in_sync`
+    struct StubMidgard {
+        url: String,
+        up: Arc<AtomicBool>,
+        in_sync: Arc<AtomicBool>,
+    }
+
+    impl StubMidgard {
+        async fn start(name: &'static str) -> Self {
+            let up = Arc::new(AtomicBool::new(true));
+            let in_sync = Arc::new(AtomicBool::new(true));
+            let app = Router::new()
+                .route(
+                    "/v2/history/swaps",
+                    get(move |State(up): State<Arc<AtomicBool>>| async move {
+                        if up.load(Ordering::SeqCst) {
+                            Ok(Json(json!({ "from": name })))
+                        } else {
+                            Err(HttpStatus::SERVICE_UNAVAILABLE)
+                        }
+                    }),
+                )
+                .with_state(up.clone())
+                .route(
+                    "/v2/health",
+                    get(move |State(in_sync): State<Arc<AtomicBool>>| async move {
+                        Json(json!({
+                            "inSync": in_sync.load(Ordering::SeqCst),
+                            "lastAggregated": { "timestamp": Utc::now().timestamp() },
+                        }))
+                    })
+                    .with_state(in_sync.clone()),
+                );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            Self { url, up, in_sync }
+        }
+    }
+
+    /// A client for `primary` then `secondary` that retries once and benches a failing instance for a second
+    fn client(primary: &StubMidgard, secondary: &StubMidgard) -> MidgardHttp {
+        let mut settings = test_support::settings(&[]);
+        settings.midgard_base_urls = vec![primary.url.clone(), secondary.url.clone()];
+        settings.midgard_max_retries = 1;
+        settings.midgard_unhealthy_cooldown_secs = 1;
+        MidgardHttp::new(&settings)
+    }
+
+    async fn answered_by(http: &MidgardHttp) -> String {
+        http.get_json("history/swaps").await.unwrap()["from"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn rate_limiter_rejects_rates_it_cannot_space() {
+        for rate in [0.0, -1.0, f64::NAN, f64::NEG_INFINITY, 1e-320] {
+            assert!(RateLimiter::new(rate).is_err(), "{} was accepted", rate);
+        }
+        assert_eq!(RateLimiter::new(2.0).unwrap().spacing, Duration::from_millis(500));
+        assert_eq!(RateLimiter::new(f64::INFINITY).unwrap().spacing, Duration::ZERO);
+    }
+
+    #[test]
+    fn backoff_doubles_per_attempt_up_to_the_cap() {
+        let policy = RetryPolicy { max_retries: 5, base_delay: Duration::from_millis(100), max_delay: Duration::from_millis(1000) };
+        for (attempt, full) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000), (10, 1000), (40, 1000)] {
+            for _ in 0..20 {
+                let delay = policy.backoff(attempt);
+                let full = Duration::from_millis(full);
+                assert!(delay >= full / 2 && delay <= full, "attempt {}: {:?} outside {:?}", attempt, delay, full);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_http_dates() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+            headers
+        };
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+
+        let in_a_minute = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let wait = retry_after(&headers(&in_a_minute)).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60), "{:?}", wait);
+
+        // Dates in the past and values we can't read mean no wait was asked for
+        assert_eq!(retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn only_throttling_timeouts_and_server_errors_are_retried() {
+        for status in [429, 408, 500, 502, 503, 504] {
+            assert!(is_retryable(StatusCode::from_u16(status).unwrap()), "{} isn't retried", status);
+        }
+        for status in [400, 401, 403, 404, 422] {
+            assert!(!is_retryable(StatusCode::from_u16(status).unwrap()), "{} is retried", status);
+        }
+    }
+
+    #[test]
+    fn health_is_stale_when_out_of_sync_or_lagging() {
+        let max_lag = Duration::from_secs(600);
+        let now = Utc::now().timestamp();
+        assert_eq!(stale_reason(&json!({ "inSync": true, "lastAggregated": { "timestamp": now } }), max_lag), None);
+        assert_eq!(stale_reason(&json!({ "inSync": false }), max_lag).as_deref(), Some("Midgard is not in sync"));
+
+        let lagging = stale_reason(&json!({ "lastAggregated": { "timestamp": (now - 3600).to_string() } }), max_lag).unwrap();
+        assert!(lagging.starts_with("last aggregated block is"), "{}", lagging);
+        // Instances that don't report a timestamp are trusted
+        assert_eq!(stale_reason(&json!({}), max_lag), None);
+    }
+
+    #[tokio::test]
+    async fn requests_fail_over_and_return_once_the_primary_recovers() {
+        let primary = StubMidgard::start("primary").await;
+        let secondary = StubMidgard::start("secondary").await;
+        let http = client(&primary, &secondary);
+        assert_eq!(answered_by(&http).await, "primary");
+
+        primary.up.store(false, Ordering::SeqCst);
+        assert_eq!(answered_by(&http).await, "secondary");
+
+        // The primary sits out its cooldown even once it is back
+        primary.up.store(true, Ordering::SeqCst);
+        assert_eq!(answered_by(&http).await, "secondary");
+
+        sleep(Duration::from_millis(1100)).await;
+        assert_eq!(answered_by(&http).await, "primary");
+    }
+
+    #[tokio::test]
+    async fn a_stale_primary_is_skipped_until_it_catches_up() {
+        let primary = StubMidgard::start("primary").await;
+        let secondary = StubMidgard::start("secondary").await;
+        primary.in_sync.store(false, Ordering::SeqCst);
+        let http = client(&primary, &secondary);
+        assert_eq!(answered_by(&http).await, "secondary");
+
+        primary.in_sync.store(true, Ordering::SeqCst);
+        sleep(Duration::from_millis(1100)).await;
+        assert_eq!(answered_by(&http).await, "primary");
+    }
+}
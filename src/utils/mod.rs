@@ -1,2 +1,10 @@
+pub mod backfill;
 pub mod conversion;
-pub mod midgard_fetch;
\ No newline at end of file
+pub mod integrity;
+pub mod legacy;
+pub mod midgard_fetch;
+pub mod midgard_http;
+pub mod midgard_client;
+pub mod midgard_fixture;
+#[cfg(test)]
+pub mod test_support;
\ No newline at end of file
@@ -0,0 +1,107 @@
+//! Midgard recordings for tests: each `FixtureDir` is a fresh directory that `FixtureMidgardClient` replays
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::{json, Map, Value};
+
+use crate::config::settings::Settings;
+use crate::utils::midgard_fixture::FixtureMidgardClient;
+
+/// Fields of a Midgard swaps interval (and of its `meta`) besides the timestamps
+const SWAPS_FIELDS: [&str; 19] = [
+    "toAssetCount", "toRuneCount", "toTradeCount", "fromTradeCount", "toSecuredCount", "fromSecuredCount", "synthMintCount",
+    "synthRedeemCount", "totalCount", "toAssetVolume", "toRuneVolume", "toTradeVolume", "fromTradeVolume", "toSecuredVolume",
+    "fromSecuredVolume", "synthMintVolume", "synthRedeemVolume", "totalVolume", "runePriceUSD",
+];
+
+const DEPTH_FIELDS: [&str; 10] = [
+    "assetDepth", "assetPrice", "assetPriceUSD", "liquidityUnits", "membersCount", "runeDepth", "synthSupply", "synthUnits", "units", "luvi",
+];
+
+const DEPTH_META_FIELDS: [&str; 12] = [
+    "endAssetDepth", "endLPUnits", "endMemberCount", "endRuneDepth", "endSynthUnits", "luviIncrease", "priceShiftLoss",
+    "startAssetDepth", "startLPUnits", "startMemberCount", "startRuneDepth", "startSynthUnits",
+];
+
+/// Distinguishes the directories of tests running in parallel within one process
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary recording directory, removed when dropped
+pub struct FixtureDir {
+    path: PathBuf,
+}
+
+impl FixtureDir {
+    pub fn new() -> Self {
+        let name = format!("midgard-fixtures-{}-{}", std::process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn client(&self) -> FixtureMidgardClient {
+        FixtureMidgardClient::new(&self.path)
+    }
+
+    /// Writes `<name>.json`
+    pub fn write(&self, name: &str, response: &Value) {
+        std::fs::write(self.path.join(format!("{}.json", name)), response.to_string()).unwrap();
+    }
+
+    /// `swaps.json` with one hourly interval per `(startTime, value)`, every count and volume set to `value`
+    pub fn record_swaps(&self, hours: &[(i64, &str)]) {
+        self.write("swaps", &recording(hours, &SWAPS_FIELDS, &SWAPS_FIELDS));
+    }
+
+    /// `depths.<pool>.json` with one hourly interval per `(startTime, value)`
+    pub fn record_depths(&self, pool: &str, hours: &[(i64, &str)]) {
+        self.write(&format!("depths.{}", pool), &recording(hours, &DEPTH_FIELDS, &DEPTH_META_FIELDS));
+    }
+}
+
+impl Drop for FixtureDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A Midgard history response; `meta` only needs to decode, the client rewrites its time range
+fn recording(hours: &[(i64, &str)], fields: &[&str], meta_fields: &[&str]) -> Value {
+    let interval = |start_time: i64, value: &str| {
+        let mut interval = Map::new();
+        interval.insert("startTime".to_string(), Value::String(start_time.to_string()));
+        interval.insert("endTime".to_string(), Value::String((start_time + 3600).to_string()));
+        for field in fields {
+            interval.insert(field.to_string(), Value::String(value.to_string()));
+        }
+        Value::Object(interval)
+    };
+    let intervals: Vec<Value> = hours.iter().map(|(start_time, value)| interval(*start_time, value)).collect();
+    let mut meta: Map<String, Value> = meta_fields.iter().map(|field| (field.to_string(), Value::String("1".to_string()))).collect();
+    meta.insert("startTime".to_string(), Value::String("0".to_string()));
+    meta.insert("endTime".to_string(), Value::String("0".to_string()));
+    json!({ "meta": meta, "intervals": intervals })
+}
+
+/// Settings for ingesting `pools` without reading the environment
+pub fn settings(pools: &[&str]) -> Settings {
+    Settings {
+        store_backend: "memory".to_string(),
+        mongo_uri: String::new(),
+        port: 0,
+        api_max_limit: 400,
+        midgard_base_urls: vec![],
+        midgard_max_lag_secs: 600,
+        midgard_unhealthy_cooldown_secs: 60,
+        midgard_fixtures_dir: None,
+        midgard_pools: pools.iter().map(|pool| pool.to_string()).collect(),
+        midgard_max_retries: 0,
+        midgard_backoff_base_ms: 1,
+        midgard_backoff_max_ms: 10,
+        midgard_requests_per_second: 1000.0,
+        midgard_timeout_secs: 5,
+        integrity_check_interval_secs: 0,
+        integrity_auto_repair: false,
+    }
+}